@@ -0,0 +1,217 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::backup::{self, BackupPolicy};
+use crate::bootstrap::{self, Origin};
+use crate::discovery;
+use crate::drift::{self, BaseStore};
+use crate::filesystem::RealFileSystem;
+use crate::hooks::HookRunner;
+use crate::linker::{self, LinkState, LinkageOptions, LinkageResult, TargetRoot};
+use crate::linkfile::{self, Dotfiles};
+use crate::plan::Plan;
+use crate::secrets::CommandDecryptor;
+use crate::state::{self, State};
+use crate::{printer, prune, sync, watch, Error};
+
+use crate::cli::{BackupsCommand, Cli, Command, Mode, Parser};
+
+fn save_zelda(args: &Cli) -> Result<(), Error> {
+    printer::set_colors(args.colors());
+    let out = &mut io::stdout().lock();
+
+    match &args.command {
+        Some(Command::Init { source, into }) => return init(out, args, source, into),
+        Some(Command::Schema) => {
+            write!(out, "{}", linkfile::schema()).map_err(Error::Output)?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    let plan = match &args.command {
+        Some(Command::Apply { plan }) => Some(Plan::load(plan)?),
+        _ => None,
+    };
+
+    let linkfile = match (&plan, &args.linkfile) {
+        (Some(plan), _) => plan.linkfile.clone(),
+        (None, Some(linkfile)) => linkfile.clone(),
+        (None, None) => discovery::discover()?.ok_or(Error::MissingLinkfile)?,
+    };
+
+    let dotfiles = args.load_dotfiles(&linkfile)?;
+    let options = args.linkage_options();
+    let report = args.report(&dotfiles.root);
+    let mode = plan.as_ref().map_or(args.mode(), |plan| plan.mode);
+    let target_root = &plan.as_ref().map_or_else(|| options.target_root.clone(), |plan| plan.target_root.clone());
+
+    // Links made under an alternate root do not belong to the current user
+    let state_path = if target_root.is_default() {
+        state::state_path(&dotfiles.linkfile_path)
+    } else {
+        None
+    };
+    let mut state = match &state_path {
+        Some(state_path) => State::load(state_path).map_err(Error::State)?,
+        None => State::default(),
+    };
+    let options = LinkageOptions { deployed: state.deployed(), ..options };
+
+    match &args.command {
+        None | Some(Command::Apply { .. }) => {
+            let plan = match plan {
+                Some(plan) => plan,
+                None => linker::plan(&dotfiles, &options)?,
+            };
+            if args.show_ignored && matches!(plan.mode, Mode::Dry) {
+                let ignored = linker::find_ignored(&dotfiles, &options);
+                printer::present_ignored(out, &ignored).map_err(Error::Output)?;
+            }
+            let result = linker::apply(&dotfiles, plan);
+            printer::present_result(out, &result, &report).map_err(Error::Output)?;
+            if let LinkageResult::Completed(link_states, _) = &result {
+                record(&mut state, &dotfiles, target_root, link_states)?;
+            }
+        }
+        Some(Command::Status) => {
+            writeln!(out, "Linkfile: {}", dotfiles.linkfile_path.display()).map_err(Error::Output)?;
+            let link_tasks = linker::examine(&dotfiles, &options)?;
+            printer::present_link_tasks(out, &link_tasks, &report).map_err(Error::Output)?;
+        }
+        Some(Command::Plan { output }) => {
+            let plan = linker::plan(&dotfiles, &options)?;
+            if args.show_ignored {
+                let ignored = linker::find_ignored(&dotfiles, &options);
+                printer::present_ignored(out, &ignored).map_err(Error::Output)?;
+            }
+            printer::present_plan(out, &plan, &report).map_err(Error::Output)?;
+            if let Some(output) = output {
+                plan.save(output)?;
+            }
+        }
+        Some(Command::Prune) => {
+            let link_tasks = linker::examine(&dotfiles, &options)?;
+            let stale_links = prune::find_stale_links(&RealFileSystem, &dotfiles, target_root, &state)
+                .map_err(Error::State)?;
+            let hook_runner = HookRunner::new(&dotfiles.root, mode, &dotfiles.linkfile.meta.hooks);
+            let (prune_states, hook_runs) =
+                prune::prune_links(&RealFileSystem, mode, stale_links, &link_tasks, &hook_runner, &mut state);
+            let directory_states = prune::prune_directories(&RealFileSystem, mode, &prune_states, &mut state);
+            if !matches!(mode, Mode::Dry) && target_root.is_default() {
+                prune::forget_stale_copies(&RealFileSystem, &dotfiles, target_root, &mut state);
+                if let Some(base_dir) = state::base_dir(&dotfiles.linkfile_path) {
+                    let store = BaseStore::new(&RealFileSystem, &base_dir, dotfiles.linkfile.meta.merge_base);
+                    store.collect_garbage(&state).map_err(Error::State)?;
+                }
+            }
+            printer::present_prune(out, &prune_states, &directory_states, &hook_runs).map_err(Error::Output)?;
+        }
+        Some(Command::Watch { debounce }) => {
+            let mut conflicts = Vec::new();
+            watch::watch(&dotfiles.root, Duration::from_millis(*debounce), || {
+                if let Err(err) = watch::relink(out, &dotfiles, &options, &report, &mut state, &mut conflicts) {
+                    eprintln!("{}", err);
+                }
+                if let Some(state_path) = &state_path {
+                    if let Err(err) = state.save(state_path) {
+                        eprintln!("{}", Error::State(err));
+                    }
+                }
+            })?;
+        }
+        Some(Command::Init { .. } | Command::Schema) => unreachable!("Linkfile is not needed"),
+        Some(Command::Sync { prune }) => {
+            let synced = sync::sync(&dotfiles, &options, *prune, &mut state)?;
+            printer::present_changes(out, &synced.changes).map_err(Error::Output)?;
+            printer::present_result(out, &synced.result, &report).map_err(Error::Output)?;
+            if *prune {
+                printer::present_prune(out, &synced.prune_states, &synced.directory_states, &synced.hook_runs)
+                    .map_err(Error::Output)?;
+            }
+        }
+        Some(Command::Drift { merge }) => {
+            let link_tasks = linker::examine(&dotfiles, &options)?;
+            let decryptor = CommandDecryptor::new(&dotfiles.root, &dotfiles.linkfile.meta);
+            let base_dir = state::base_dir(&dotfiles.linkfile_path).unwrap_or_default();
+            let store = BaseStore::new(&RealFileSystem, &base_dir, dotfiles.linkfile.meta.merge_base);
+            let drift_states = drift::examine_drift(&RealFileSystem, &decryptor, &store, link_tasks, &state);
+            printer::present_drift(out, &drift_states, *merge).map_err(Error::Output)?;
+        }
+        Some(Command::Backups(command)) => {
+            let targets: Vec<_> = linker::examine(&dotfiles, &options)?
+                .into_iter()
+                .map(|link_task| link_task.target)
+                .collect();
+            let policy = BackupPolicy::new(&dotfiles.linkfile.meta, target_root);
+            let result = match command {
+                BackupsCommand::List => backup::list_backups(&RealFileSystem, &policy, &targets),
+                BackupsCommand::Restore { targets: selected } => {
+                    backup::restore_backups(&RealFileSystem, mode, &policy, target_root, &targets, selected)
+                }
+                BackupsCommand::Clean { keep } => {
+                    backup::clean_backups(&RealFileSystem, mode, &policy, &targets, *keep)
+                }
+            };
+            printer::present_backups(out, &result.map_err(Error::Backup)?).map_err(Error::Output)?;
+        }
+    }
+
+    if let Some(state_path) = &state_path {
+        if !matches!(mode, Mode::Dry) {
+            state.save(state_path).map_err(Error::State)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches the dotfiles and links them as any other linkfile
+fn init(out: &mut dyn Write, args: &Cli, source: &str, into: &Path) -> Result<(), Error> {
+    let options = args.linkage_options();
+    let mut state = State::default();
+    let initialized = bootstrap::init(&Origin::detect(source), into, args.format(), &options, &mut state)?;
+
+    let dotfiles = &initialized.dotfiles;
+    let report = args.report(&dotfiles.root);
+    writeln!(out, "Linkfile: {}", dotfiles.linkfile_path.display()).map_err(Error::Output)?;
+    printer::present_plan(out, &initialized.plan, &report).map_err(Error::Output)?;
+    let result = match &initialized.result {
+        Some(result) => result,
+        None => return Ok(()),
+    };
+
+    printer::present_result(out, result, &report).map_err(Error::Output)?;
+    if options.target_root.is_default() {
+        if let Some(state_path) = state::state_path(&dotfiles.linkfile_path) {
+            state.save(&state_path).map_err(Error::State)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remembers links and copies made by the linkage
+fn record(
+    state: &mut State,
+    dotfiles: &Dotfiles,
+    target_root: &TargetRoot,
+    link_states: &[LinkState],
+) -> Result<(), Error> {
+    state.record_linkage(&RealFileSystem, dotfiles, target_root, link_states).map_err(Error::State)
+}
+
+/// Runs the command given on the command line, exits on failure
+pub fn run() {
+    let args = Cli::parse();
+    match save_zelda(&args) {
+        Ok(()) => {}
+        // Reader of the output, e.g. `head`, is gone
+        Err(Error::Output(err)) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
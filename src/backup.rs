@@ -0,0 +1,538 @@
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::filesystem::{FileSystem, NodeType};
+use crate::linker::{Mode, TargetRoot};
+use crate::linkfile::{BackupNaming, Meta};
+
+const BACKUP_SUFFIX: &str = ".bak";
+
+/// Decides where and under which name overridden targets are preserved
+///
+/// Backups are placed next to the target unless `[meta] backup_dir` is set,
+/// in that case every run moves them into its own timestamped directory
+/// mirroring the absolute path of the target, e.g. `~/.vimrc` goes to
+/// `<backup_dir>/<timestamp>/home/user/`. Relative `backup_dir` is taken from
+/// the home directory, it is placed under the alternate target root as well.
+#[derive(Debug)]
+pub struct BackupPolicy {
+    dir: Option<PathBuf>,
+    /// Where sources replaced by `adopt` go when `dir` is not set
+    sources_dir: Option<PathBuf>,
+    destdir: Option<PathBuf>,
+    naming: BackupNaming,
+    timestamp: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Backup {
+    pub target: PathBuf,
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum BackupResult {
+    Present,
+    ToRestore,
+    ToRemove,
+    Restored,
+    Removed,
+    IoError(io::Error),
+}
+
+#[derive(Debug)]
+pub struct BackupState {
+    pub backup: Backup,
+    pub result: BackupResult,
+}
+
+impl BackupPolicy {
+    pub fn new(meta: &Meta, target_root: &TargetRoot) -> Self {
+        BackupPolicy {
+            dir: meta.backup_dir.as_ref().map(|dir| {
+                let dir = target_root.expand_home(Path::new(dir));
+                match dir.is_absolute() {
+                    true => target_root.resolve(&dir),
+                    false => target_root.resolve(&Path::new("~").join(dir)),
+                }
+            }),
+            sources_dir: dirs::data_local_dir().map(|dir| {
+                let dir = dir.join("rinku").join("backups");
+                let in_home = dirs::home_dir().and_then(|home| dir.strip_prefix(home).ok().map(Path::to_path_buf));
+                match in_home {
+                    Some(in_home) => target_root.resolve(&Path::new("~").join(in_home)),
+                    None => target_root.resolve(&dir),
+                }
+            }),
+            destdir: target_root.destdir.clone(),
+            naming: meta.backup_naming,
+            timestamp: chrono::Local::now().format("%Y%m%dT%H%M%S").to_string(),
+        }
+    }
+
+    /// Moves target out of the way and returns the path of the backup
+    pub fn backup(&self, fs: &dyn FileSystem, target: &Path) -> io::Result<PathBuf> {
+        let location = match &self.dir {
+            Some(dir) => mirror(&dir.join(&self.timestamp), self.inner_parent(target)?),
+            None => self.inner_parent(target)?.to_path_buf(),
+        };
+        self.move_aside(fs, target, &location)
+    }
+
+    /// Moves the source replaced by `adopt` out of the linkfile directory
+    /// into the backup directory, or `rinku/backups` of the local data
+    /// directory when none is set, and returns the path of the backup
+    ///
+    /// The local data directory follows the alternate home and destdir.
+    pub fn backup_source(&self, fs: &dyn FileSystem, source: &Path) -> io::Result<PathBuf> {
+        let dir = self.dir.as_ref().or(self.sources_dir.as_ref()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "local data directory is unknown")
+        })?;
+        let parent = source.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("source path has no parent: {}", source.display()),
+            )
+        })?;
+        self.move_aside(fs, source, &mirror(&dir.join(&self.timestamp), parent))
+    }
+
+    fn move_aside(&self, fs: &dyn FileSystem, path: &Path, location: &Path) -> io::Result<PathBuf> {
+        let name = file_name(path)?;
+
+        let backup_path = match self.naming {
+            BackupNaming::Numbered => (1..)
+                .map(|i| location.join(backup_name(name, Some(&i.to_string()))))
+                .find(|path| !is_occupied(fs, path))
+                .expect("Infinite sequence of backup names"),
+            BackupNaming::Timestamp => location.join(backup_name(name, Some(&self.timestamp))),
+            BackupNaming::None => location.join(backup_name(name, None)),
+        };
+
+        if is_occupied(fs, &backup_path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Backup already exists: {}", backup_path.display()),
+            ));
+        }
+
+        fs.create_dir_all(location)?;
+        move_node(fs, path, &backup_path)?;
+
+        Ok(backup_path)
+    }
+
+    /// Finds all backups of the target ordered from the oldest to the newest
+    pub fn list(&self, fs: &dyn FileSystem, target: &Path) -> io::Result<Vec<Backup>> {
+        let parent = self.inner_parent(target)?;
+        let locations = match &self.dir {
+            Some(dir) => read_dir(fs, dir)?.iter().map(|run| mirror(run, parent)).collect(),
+            None => vec![parent.to_path_buf()],
+        };
+
+        let plain_name = backup_name(file_name(target)?, None);
+        let mut prefix = plain_name.clone();
+        prefix.push(".");
+
+        let mut backups = Vec::new();
+        for location in locations {
+            for path in read_dir(fs, &location)? {
+                let entry_name = path.file_name().unwrap_or_default();
+                let is_backup = entry_name == plain_name
+                    || entry_name
+                        .to_string_lossy()
+                        .starts_with(prefix.to_string_lossy().as_ref());
+                if is_backup {
+                    backups.push(Backup { target: target.to_path_buf(), path });
+                }
+            }
+        }
+
+        backups.sort_by(compare_backups);
+        Ok(backups)
+    }
+
+    /// Removes the run directory and its subdirectories the removed backup
+    /// left empty, nested ones go first
+    fn remove_empty_parents(&self, fs: &dyn FileSystem, backup: &Path) -> io::Result<()> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let parents = backup.ancestors().skip(1).take_while(|parent| parent.starts_with(dir) && parent != dir);
+        for parent in parents {
+            if !fs.read_dir(parent)?.is_empty() {
+                break;
+            }
+            fs.remove_dir(parent)?;
+        }
+        Ok(())
+    }
+
+    /// Parent of the target, the mirrored one is inside of the destdir
+    fn inner_parent<'a>(&self, target: &'a Path) -> io::Result<&'a Path> {
+        let parent = target.parent().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("target path has no parent: {}", target.display()),
+            )
+        })?;
+
+        Ok(self
+            .destdir
+            .as_ref()
+            .and_then(|destdir| parent.strip_prefix(destdir).ok())
+            .unwrap_or(parent))
+    }
+}
+
+/// Lists backups of the targets
+pub fn list_backups(
+    fs: &dyn FileSystem,
+    policy: &BackupPolicy,
+    targets: &[PathBuf],
+) -> io::Result<Vec<BackupState>> {
+    let backup_states = list_all(fs, policy, targets)?
+        .into_iter()
+        .flatten()
+        .map(|backup| BackupState { backup, result: BackupResult::Present })
+        .collect();
+
+    Ok(backup_states)
+}
+
+/// Restores the latest backups of the `selected` targets, all targets with
+/// backups are restored when nothing is selected
+pub fn restore_backups(
+    fs: &dyn FileSystem,
+    mode: Mode,
+    policy: &BackupPolicy,
+    target_root: &TargetRoot,
+    targets: &[PathBuf],
+    selected: &[PathBuf],
+) -> io::Result<Vec<BackupState>> {
+    // Targets may be given either as in the linkfile or already resolved
+    let selected: Vec<PathBuf> = selected
+        .iter()
+        .flat_map(|t| [target_root.expand_home(t), target_root.resolve(t)])
+        .collect();
+
+    let backup_states = list_all(fs, policy, targets)?
+        .into_iter()
+        .filter_map(|mut backups| backups.pop())
+        .filter(|backup| selected.is_empty() || selected.contains(&backup.target))
+        .map(|backup| {
+            let result = match mode {
+                Mode::Dry => BackupResult::ToRestore,
+                _ => restore(fs, &backup)
+                    .and_then(|_| policy.remove_empty_parents(fs, &backup.path))
+                    .map_or_else(BackupResult::IoError, |_| BackupResult::Restored),
+            };
+            BackupState { backup, result }
+        })
+        .collect();
+
+    Ok(backup_states)
+}
+
+/// Removes all but `keep` most recent backups of every target
+pub fn clean_backups(
+    fs: &dyn FileSystem,
+    mode: Mode,
+    policy: &BackupPolicy,
+    targets: &[PathBuf],
+    keep: usize,
+) -> io::Result<Vec<BackupState>> {
+    let backup_states = list_all(fs, policy, targets)?
+        .into_iter()
+        .flat_map(|backups| {
+            let outdated = backups.len().saturating_sub(keep);
+            backups.into_iter().take(outdated)
+        })
+        .map(|backup| {
+            let result = match mode {
+                Mode::Dry => BackupResult::ToRemove,
+                _ => fs
+                    .remove_all(&backup.path)
+                    .and_then(|_| policy.remove_empty_parents(fs, &backup.path))
+                    .map_or_else(BackupResult::IoError, |_| BackupResult::Removed),
+            };
+            BackupState { backup, result }
+        })
+        .collect();
+
+    Ok(backup_states)
+}
+
+fn list_all(fs: &dyn FileSystem, policy: &BackupPolicy, targets: &[PathBuf]) -> io::Result<Vec<Vec<Backup>>> {
+    targets.iter().map(|target| policy.list(fs, target)).collect()
+}
+
+/// Puts backup back in place of the target, the target may only be absent
+/// or a symlink, real files and directories are never removed
+fn restore(fs: &dyn FileSystem, backup: &Backup) -> io::Result<()> {
+    match fs.symlink_node_type(&backup.target) {
+        Ok(NodeType::Symlink) => fs.remove_link(&backup.target)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "Target exists and is not a symlink",
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    move_node(fs, &backup.path, &backup.target)
+}
+
+/// Renames the node, it is copied and removed when the destination is on
+/// another device
+fn move_node(fs: &dyn FileSystem, from: &Path, to: &Path) -> io::Result<()> {
+    match fs.rename(from, to) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            copy_node(fs, from, to)?;
+            fs.remove_all(from)
+        }
+        result => result,
+    }
+}
+
+/// Copies the node with everything inside, symlinks are copied as they are
+fn copy_node(fs: &dyn FileSystem, from: &Path, to: &Path) -> io::Result<()> {
+    // Permissions are kept where the platform has them
+    let mode = match fs.stat(from) {
+        Ok(stat) => Some(stat.mode),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => None,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    match fs.symlink_node_type(from)? {
+        NodeType::Symlink => fs.symlink(&fs.read_link(from)?, to),
+        NodeType::File => fs.create_file(to, &fs.read(from)?, mode.unwrap_or(0o644)),
+        NodeType::Dir => {
+            fs.create_dir_all(to)?;
+            for entry in fs.read_dir(from)? {
+                let name = entry.file_name().expect("Entry of the directory has a name");
+                copy_node(fs, &entry, &to.join(name))?;
+            }
+            mode.map_or(Ok(()), |mode| fs.set_mode(to, mode))
+        }
+    }
+}
+
+/// Entries of the directory, a missing one is empty
+fn read_dir(fs: &dyn FileSystem, path: &Path) -> io::Result<Vec<PathBuf>> {
+    match fs.read_dir(path) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        result => result,
+    }
+}
+
+fn compare_backups(l: &Backup, r: &Backup) -> Ordering {
+    // Runs are ordered by their timestamps, numbered backups of a run by
+    // value, `.bak.10` is newer than `.bak.9`
+    let key = |backup: &Backup| {
+        let name = backup.path.to_string_lossy().into_owned();
+        let suffix = name.rsplit('.').next().unwrap_or_default().to_string();
+        (backup.path.parent().map(Path::to_path_buf), suffix.parse::<u64>().ok(), name)
+    };
+
+    key(l).cmp(&key(r))
+}
+
+fn backup_name(name: &std::ffi::OsStr, suffix: Option<&str>) -> OsString {
+    let mut backup_name = name.to_os_string();
+    backup_name.push(BACKUP_SUFFIX);
+    if let Some(suffix) = suffix {
+        backup_name.push(".");
+        backup_name.push(suffix);
+    }
+    backup_name
+}
+
+fn file_name(target: &Path) -> io::Result<&std::ffi::OsStr> {
+    target.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("target path has no file name: {}", target.display()),
+        )
+    })
+}
+
+fn is_occupied(fs: &dyn FileSystem, path: &Path) -> bool {
+    fs.symlink_node_type(path).is_ok()
+}
+
+pub(crate) fn mirror(dir: &Path, path: &Path) -> PathBuf {
+    let mut mirrored = dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => {
+                let prefix = prefix.as_os_str().to_string_lossy().replace([':', '\\', '?'], "");
+                mirrored.push(prefix);
+            }
+            Component::RootDir => {}
+            component => mirrored.push(component),
+        }
+    }
+    mirrored
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::filesystem::{MemoryFileSystem, MemoryNode};
+
+    fn policy(backup_dir: Option<&str>, timestamp: &str) -> BackupPolicy {
+        let meta = Meta { backup_dir: backup_dir.map(str::to_string), ..Meta::default() };
+        let target_root = TargetRoot { home: Some(PathBuf::from("/home/link")), destdir: None };
+        BackupPolicy { timestamp: timestamp.to_string(), ..BackupPolicy::new(&meta, &target_root) }
+    }
+
+    fn paths(backup_states: &[BackupState]) -> Vec<String> {
+        backup_states.iter().map(|state| state.backup.path.display().to_string()).collect()
+    }
+
+    #[test]
+    fn mirror_absolute_path() {
+        assert_eq!(
+            mirror(Path::new("/backups"), Path::new("/home/link/.config")),
+            PathBuf::from("/backups/home/link/.config")
+        );
+    }
+
+    #[test]
+    fn numbered_backups_order() {
+        let backup = |path: &str| Backup {
+            target: PathBuf::from("/home/link/.vimrc"),
+            path: PathBuf::from(path),
+        };
+
+        let mut backups = vec![
+            backup("/backups/20250102T000000/home/link/.vimrc.bak.1"),
+            backup("/home/link/.vimrc.bak.10"),
+            backup("/home/link/.vimrc.bak.9"),
+            backup("/backups/20250101T000000/home/link/.vimrc.bak.2"),
+            backup("/home/link/.vimrc.bak.1"),
+        ];
+        backups.sort_by(compare_backups);
+
+        assert_eq!(
+            backups,
+            vec![
+                backup("/backups/20250101T000000/home/link/.vimrc.bak.2"),
+                backup("/backups/20250102T000000/home/link/.vimrc.bak.1"),
+                backup("/home/link/.vimrc.bak.1"),
+                backup("/home/link/.vimrc.bak.9"),
+                backup("/home/link/.vimrc.bak.10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_backup_dir_is_in_home_with_directory_per_run() {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/home/link/.vimrc");
+
+        let first = policy(Some("backups"), "20250101T000000");
+        let path = first.backup(&fs, Path::new("/home/link/.vimrc")).unwrap();
+        fs.add_file("/home/link/.vimrc");
+        let second = policy(Some("backups"), "20250102T000000");
+        second.backup(&fs, Path::new("/home/link/.vimrc")).unwrap();
+
+        assert_eq!(path, PathBuf::from("/home/link/backups/20250101T000000/home/link/.vimrc.bak.1"));
+        let backups = list_backups(&fs, &second, &[PathBuf::from("/home/link/.vimrc")]).unwrap();
+        assert_eq!(
+            paths(&backups),
+            vec![
+                "/home/link/backups/20250101T000000/home/link/.vimrc.bak.1",
+                "/home/link/backups/20250102T000000/home/link/.vimrc.bak.1",
+            ]
+        );
+    }
+
+    #[test]
+    fn backup_to_other_device_is_copied() {
+        let mut fs = MemoryFileSystem::new();
+        fs.mount("/mnt/backups");
+        fs.add_file_content("/home/link/.config/nvim/init.lua", b"vim.o.number = true");
+        fs.add_symlink("/home/link/.config/nvim/lua", "/opt/lua");
+
+        let policy = policy(Some("/mnt/backups"), "20250101T000000");
+        let path = policy.backup(&fs, Path::new("/home/link/.config/nvim")).unwrap();
+
+        assert_eq!(path, PathBuf::from("/mnt/backups/20250101T000000/home/link/.config/nvim.bak.1"));
+        assert_eq!(fs.content(path.join("init.lua")), Some(b"vim.o.number = true".to_vec()));
+        assert_eq!(fs.node(path.join("lua")), Some(MemoryNode::Symlink(PathBuf::from("/opt/lua"))));
+        assert_eq!(fs.node("/home/link/.config/nvim"), None);
+    }
+
+    #[test]
+    fn restore_and_clean_backups() {
+        let fs = MemoryFileSystem::new();
+        let target = PathBuf::from("/home/link/.vimrc");
+        let targets = [target.clone()];
+        let policy = policy(None, "20250101T000000");
+        for content in [b"old", b"new"] {
+            fs.add_file_content(&target, content);
+            policy.backup(&fs, &target).unwrap();
+        }
+        fs.add_symlink(&target, "/dotfiles/vimrc");
+
+        let cleaned = clean_backups(&fs, Mode::Dry, &policy, &targets, 1).unwrap();
+        assert!(matches!(cleaned[0].result, BackupResult::ToRemove));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.1"), Some(MemoryNode::File));
+
+        let cleaned = clean_backups(&fs, Mode::Lazy, &policy, &targets, 1).unwrap();
+        assert_eq!(paths(&cleaned), vec!["/home/link/.vimrc.bak.1"]);
+        assert!(matches!(cleaned[0].result, BackupResult::Removed));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.1"), None);
+
+        let restored = restore_backups(&fs, Mode::Lazy, &policy, &TargetRoot::default(), &targets, &[]).unwrap();
+        assert_eq!(paths(&restored), vec!["/home/link/.vimrc.bak.2"]);
+        assert!(matches!(restored[0].result, BackupResult::Restored));
+        assert_eq!(fs.content(&target), Some(b"new".to_vec()));
+
+        // Real files are never replaced
+        fs.add_file("/home/link/.vimrc.bak.3");
+        let restored = restore_backups(&fs, Mode::Lazy, &policy, &TargetRoot::default(), &targets, &[]).unwrap();
+        assert!(matches!(restored[0].result, BackupResult::IoError(_)));
+        assert_eq!(fs.content(&target), Some(b"new".to_vec()));
+    }
+
+    #[test]
+    fn emptied_run_directories_are_removed() {
+        let fs = MemoryFileSystem::new();
+        let target = PathBuf::from("/home/link/.vimrc");
+        let targets = [target.clone()];
+        for timestamp in ["20250101T000000", "20250102T000000"] {
+            fs.add_file(&target);
+            policy(Some("/backups"), timestamp).backup(&fs, &target).unwrap();
+        }
+        fs.add_file("/backups/20250102T000000/home/link/.zshrc.bak.1");
+
+        let policy = policy(Some("/backups"), "20250103T000000");
+        let cleaned = clean_backups(&fs, Mode::Lazy, &policy, &targets, 0).unwrap();
+        assert_eq!(cleaned.len(), 2);
+        assert!(cleaned.iter().all(|state| matches!(state.result, BackupResult::Removed)));
+        assert_eq!(fs.node("/backups/20250101T000000"), None);
+        assert_eq!(fs.node("/backups/20250102T000000/home/link/.zshrc.bak.1"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/backups"), Some(MemoryNode::Dir));
+    }
+
+    #[test]
+    fn adopted_sources_are_backed_up_under_destdir() {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/vimrc");
+        let target_root = TargetRoot { home: None, destdir: Some(PathBuf::from("/image")) };
+        let policy = BackupPolicy::new(&Meta::default(), &target_root);
+
+        let path = policy.backup_source(&fs, Path::new("/dotfiles/vimrc")).unwrap();
+        assert!(path.starts_with("/image"), "{}", path.display());
+        assert_eq!(fs.node(&path), Some(MemoryNode::File));
+    }
+}
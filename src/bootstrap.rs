@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+use std::{fs, io, process};
+
+use crate::discovery;
+use crate::error::Error;
+use crate::filesystem::RealFileSystem;
+use crate::git;
+use crate::linker::{self, LinkageOptions, LinkageResult, Mode};
+use crate::linkfile::{Dotfiles, Format};
+use crate::plan::Plan;
+use crate::state::State;
+
+/// Where the dotfiles for `init` come from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    /// Remote URL or local repository, cloned with git
+    Repository(String),
+    /// Tar archive, compressed or not, extracted with tar
+    Archive(PathBuf),
+    /// Plain directory, copied as is
+    Directory(PathBuf),
+}
+
+impl Origin {
+    /// Local paths are told apart by their content, everything else is
+    /// considered a repository URL
+    pub fn detect(source: &str) -> Origin {
+        let path = Path::new(source);
+        if path.is_file() {
+            return Origin::Archive(path.to_path_buf());
+        }
+        if path.is_dir() && !is_repository(path) {
+            return Origin::Directory(path.to_path_buf());
+        }
+        Origin::Repository(source.to_string())
+    }
+}
+
+/// Directory with the working tree or the bare repository
+fn is_repository(dir: &Path) -> bool {
+    dir.join(".git").exists() || (dir.join("HEAD").is_file() && dir.join("objects").is_dir())
+}
+
+/// Dotfiles set up by `init`
+#[derive(Debug)]
+pub struct Initialized {
+    pub dotfiles: Dotfiles,
+    /// Plan of the dry mode, shown before anything is linked
+    pub plan: Plan,
+    /// Outcome of the linkage, none in the dry mode
+    pub result: Option<LinkageResult>,
+}
+
+/// Fetches the dotfiles and links them as any other linkfile
+///
+/// `~/` of the directory refers to the home of the target root, as in the
+/// targets. The linkfile is looked for among the conventional names and read
+/// in the given format or the one of its extension. Links and copies made
+/// when the mode is not dry are recorded in the state.
+pub fn init(
+    origin: &Origin,
+    into: &Path,
+    format: Option<Format>,
+    options: &LinkageOptions,
+    state: &mut State,
+) -> Result<Initialized, Error> {
+    let destination = options.target_root.expand_home(into);
+    fetch(origin, &destination).map_err(Error::Init)?;
+
+    let linkfile = discovery::find_in(&destination).ok_or_else(|| {
+        let message = format!("no linkfile found in {}", destination.display());
+        Error::Init(io::Error::new(io::ErrorKind::NotFound, message))
+    })?;
+    let dotfiles = match format {
+        Some(format) => Dotfiles::load_as(&linkfile, format)?,
+        None => Dotfiles::load(&linkfile)?,
+    };
+
+    let plan = linker::plan(&dotfiles, &LinkageOptions { mode: Mode::Dry, ..options.clone() })?;
+    if options.mode == Mode::Dry {
+        return Ok(Initialized { dotfiles, plan, result: None });
+    }
+
+    let result = linker::apply(&dotfiles, linker::plan(&dotfiles, options)?);
+    if let LinkageResult::Completed(link_states, _) = &result {
+        state
+            .record_linkage(&RealFileSystem, &dotfiles, &options.target_root, link_states)
+            .map_err(Error::State)?;
+    }
+
+    Ok(Initialized { dotfiles, plan, result: Some(result) })
+}
+
+/// Populates the destination directory, it must be absent or empty
+pub fn fetch(origin: &Origin, destination: &Path) -> io::Result<()> {
+    match fs::read_dir(destination).map(|mut entries| entries.next().is_none()) {
+        Ok(true) => fs::remove_dir(destination)?,
+        Ok(false) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", destination.display()),
+            ));
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+
+    match origin {
+        Origin::Repository(url) => git::clone(url, destination),
+        Origin::Archive(archive) => extract(archive, destination),
+        Origin::Directory(dir) => copy_dir(dir, destination),
+    }
+}
+
+/// Extracts the archive with the `tar` executable found in `PATH`
+///
+/// Single top level directory, as in archives of the git hostings, is
+/// stripped.
+fn extract(archive: &Path, destination: &Path) -> io::Result<()> {
+    let listing = tar(process::Command::new("tar").arg("-tf").arg(archive))?;
+    let listing = String::from_utf8_lossy(&listing);
+    let tops: Vec<&str> = listing
+        .lines()
+        .filter_map(|entry| entry.trim_start_matches("./").split('/').next())
+        .filter(|top| !top.is_empty())
+        .collect();
+    let is_wrapped = tops.windows(2).all(|pair| pair[0] == pair[1])
+        && listing.lines().any(|entry| entry.trim_start_matches("./").contains('/'));
+
+    fs::create_dir_all(destination)?;
+    let mut command = process::Command::new("tar");
+    command.arg("-xf").arg(archive).arg("-C").arg(destination);
+    if is_wrapped {
+        command.arg("--strip-components=1");
+    }
+    tar(&mut command).map(|_| ())
+}
+
+fn tar(command: &mut process::Command) -> io::Result<Vec<u8>> {
+    let output = command
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("tar is not available: {}", err)))?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Copies the directory tree, symlinks are copied as symlinks
+fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            copy_dir(&path, &target)?;
+        } else if file_type.is_symlink() {
+            copy_symlink(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, target)
+}
+
+#[cfg(target_family = "windows")]
+fn copy_symlink(source: &Path, target: &Path) -> io::Result<()> {
+    let destination = fs::read_link(source)?;
+    if source.is_dir() {
+        std::os::windows::fs::symlink_dir(destination, target)
+    } else {
+        std::os::windows::fs::symlink_file(destination, target)
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::discovery::find_in;
+    use crate::linker::TargetRoot;
+
+    fn run(dir: &Path, program: &str, args: &[&str]) {
+        let status = process::Command::new(program)
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "rinku")
+            .env("GIT_AUTHOR_EMAIL", "rinku@localhost")
+            .env("GIT_COMMITTER_NAME", "rinku")
+            .env("GIT_COMMITTER_EMAIL", "rinku@localhost")
+            .stdout(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} {:?}", program, args);
+    }
+
+    fn dotfiles(dir: &Path) -> PathBuf {
+        let dotfiles = dir.join("dotfiles");
+        fs::create_dir_all(dotfiles.join("nvim")).unwrap();
+        fs::write(dotfiles.join("dotfiles.toml"), "[[link]]\nsource = \"nvim\"\ntarget = \"~/.config/nvim\"\n").unwrap();
+        fs::write(dotfiles.join("nvim/init.lua"), "").unwrap();
+        dotfiles
+    }
+
+    #[test]
+    fn init_from_bare_repository() {
+        let temp = tempfile::tempdir().unwrap();
+        let dotfiles = dotfiles(temp.path());
+        run(&dotfiles, "git", &["init", "--quiet"]);
+        run(&dotfiles, "git", &["add", "."]);
+        run(&dotfiles, "git", &["commit", "--quiet", "-m", "init"]);
+        run(temp.path(), "git", &["clone", "--quiet", "--bare", "dotfiles", "dotfiles.git"]);
+
+        let origin = Origin::detect(temp.path().join("dotfiles.git").to_str().unwrap());
+        assert!(matches!(origin, Origin::Repository(_)));
+
+        let destination = temp.path().join("cloned");
+        fetch(&origin, &destination).unwrap();
+        assert_eq!(find_in(&destination), Some(destination.join("dotfiles.toml")));
+        assert!(destination.join("nvim/init.lua").is_file());
+    }
+
+    #[test]
+    fn init_from_archive() {
+        let temp = tempfile::tempdir().unwrap();
+        dotfiles(temp.path());
+        run(temp.path(), "tar", &["-czf", "dotfiles.tar.gz", "dotfiles"]);
+
+        let origin = Origin::detect(temp.path().join("dotfiles.tar.gz").to_str().unwrap());
+        assert!(matches!(origin, Origin::Archive(_)));
+
+        let destination = temp.path().join("extracted");
+        fetch(&origin, &destination).unwrap();
+        assert_eq!(find_in(&destination), Some(destination.join("dotfiles.toml")));
+        assert!(destination.join("nvim/init.lua").is_file());
+
+        let result = fetch(&origin, &destination);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn init_from_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dotfiles = dotfiles(temp.path());
+
+        let origin = Origin::detect(dotfiles.to_str().unwrap());
+        assert_eq!(origin, Origin::Directory(dotfiles));
+
+        let destination = temp.path().join("copied");
+        fetch(&origin, &destination).unwrap();
+        assert!(destination.join("nvim/init.lua").is_file());
+    }
+
+    #[test]
+    fn init_into_home_of_target_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let temp = temp.path().canonicalize().unwrap();
+        let origin = Origin::Directory(dotfiles(&temp));
+        let home = temp.join("home");
+        let target_root = TargetRoot { home: Some(home.clone()), destdir: None };
+
+        let options = LinkageOptions { target_root: target_root.clone(), ..LinkageOptions::default() };
+        let mut state = State::default();
+        let initialized = init(&origin, Path::new("~/dry"), None, &options, &mut state).unwrap();
+        assert_eq!(initialized.dotfiles.root, home.join("dry"));
+        assert_eq!(initialized.plan.links.len(), 1);
+        assert!(initialized.result.is_none());
+        assert!(fs::symlink_metadata(home.join(".config/nvim")).is_err());
+
+        let options = LinkageOptions { mode: Mode::Lazy, target_root, ..LinkageOptions::default() };
+        let initialized = init(&origin, Path::new("~/dotfiles"), None, &options, &mut state).unwrap();
+        assert!(matches!(initialized.result, Some(LinkageResult::Completed(..))));
+        assert_eq!(fs::read_link(home.join(".config/nvim")).unwrap(), home.join("dotfiles/nvim"));
+        assert_eq!(state.links.len(), 1);
+
+        let result = init(&origin, Path::new("~/dotfiles"), None, &options, &mut state);
+        assert!(matches!(result, Err(Error::Init(_))));
+    }
+}
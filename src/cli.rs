@@ -1,10 +1,18 @@
 pub use clap::Parser;
+pub use crate::linker::Mode;
 
-use clap::ValueEnum;
-use std::path;
+use clap::{Subcommand, ValueEnum};
+use crate::linker::{LinkageOptions, Strictness, Tags, TargetRoot};
+use crate::linkfile::{Dotfiles, Format};
+use crate::printer::{self, ColorChoice, Grouping, Report, Status};
+use crate::Error;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::{env, path};
 
-#[derive(Copy, Clone, ValueEnum)]
-pub enum Mode {
+/// Command line form of `linker::Mode`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ModeArg {
     /// Only prints status of the targets
     Dry,
 
@@ -14,29 +22,302 @@ pub enum Mode {
     /// Fills missing targets, ignores existing one
     Lazy,
 
-    /// Overrides all targets with copyng old versions to the `*.orig.<#>`
+    /// Overrides all targets moving old versions to backups
     Force,
 }
 
+impl From<ModeArg> for Mode {
+    fn from(mode: ModeArg) -> Mode {
+        match mode {
+            ModeArg::Dry => Mode::Dry,
+            ModeArg::Strict => Mode::Strict,
+            ModeArg::Lazy => Mode::Lazy,
+            ModeArg::Force => Mode::Force,
+        }
+    }
+}
+
+/// Command line form of `linkfile::Format`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum FormatArg {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl From<FormatArg> for Format {
+    fn from(format: FormatArg) -> Format {
+        match format {
+            FormatArg::Toml => Format::Toml,
+            FormatArg::Yaml => Format::Yaml,
+            FormatArg::Json => Format::Json,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Prints actions of the linkage without executing them
+    Plan {
+        /// Writes the plan to the file to apply it later
+        #[arg(short = 'o', long = "output")]
+        output: Option<path::PathBuf>,
+    },
+
+    /// Executes the plan written by the `plan` command
+    Apply {
+        plan: path::PathBuf,
+    },
+
+    /// Removes links into the linkfile directory that are no longer produced by it
+    Prune,
+
+    /// Links new entries in the lazy mode whenever the linkfile directory changes
+    Watch {
+        /// Quiet period after the last change before relinking
+        #[arg(long, value_name = "MS", default_value_t = 500)]
+        debounce: u64,
+    },
+
+    /// Shows state of every target
+    Status,
+
+    /// Prints JSON Schema of the linkfile
+    Schema,
+
+    /// Fetches dotfiles from a repository, an archive or a directory and shows the plan
+    ///
+    /// The plan is applied when the mode is not dry.
+    Init {
+        /// Git URL, local repository, tar archive or directory
+        source: String,
+
+        /// Directory to place the dotfiles in, `~/` follows `--home`
+        #[arg(long, value_name = "DIR", default_value = "~/dotfiles")]
+        into: path::PathBuf,
+    },
+
+    /// Fast-forwards the linkfile repository and links new entries
+    Sync {
+        /// Also prunes links of the removed entries
+        #[arg(long)]
+        prune: bool,
+    },
+
+    /// Compares deployed copies with the local edits and the upstream changes
+    Drift {
+        /// Prints three-way merge of the copies changed on both sides
+        #[arg(long)]
+        merge: bool,
+    },
+
+    /// Manages backups of the overridden targets
+    #[command(subcommand)]
+    Backups(BackupsCommand),
+}
+
+#[derive(Subcommand)]
+pub enum BackupsCommand {
+    /// Lists backups of the linkfile targets
+    List,
+
+    /// Restores the latest backup in place of the link
+    Restore {
+        /// Targets to restore, all targets with backups by default
+        targets: Vec<path::PathBuf>,
+    },
+
+    /// Removes backups of the linkfile targets
+    Clean {
+        /// Number of the most recent backups to keep for each target
+        #[arg(long, default_value_t = 0)]
+        keep: usize,
+    },
+}
+
 #[derive(Parser)]
 pub struct Cli {
-    /// e.g. dotfiles.toml
-    pub linkfile: path::PathBuf,
+    /// e.g. dotfiles.toml, found automatically when omitted
+    pub linkfile: Option<path::PathBuf>,
 
-    /// Operation mode
+    /// Operation mode, dry by default, lazy for `watch` and `sync`
     #[arg(
         value_enum,
         short = 'm',
         long = "mode",
-        default_value_t = Mode::Dry
+        global = true
     )]
-    pub mode: Mode,
+    pub mode: Option<ModeArg>,
 
     /// Explicitly specified tags
     #[arg(
         short = 't',
         long = "tags",
+        global = true,
         value_delimiter = ','
     )]
     pub tags: Vec<String>,
+
+    /// Continue with other links when some of the sources are missing
+    #[arg(short = 'k', long = "keep-going", global = true)]
+    pub keep_going: bool,
+
+    /// Relink targets that reach the source through intermediate symlinks
+    #[arg(long = "normalize", global = true)]
+    pub normalize: bool,
+
+    /// Link as root even when sources are writable by other users
+    #[arg(long = "allow-unsafe-sources", global = true)]
+    pub allow_unsafe_sources: bool,
+
+    /// Strict mode lets already linked targets through
+    #[arg(long = "allow-linked", global = true)]
+    pub allow_linked: bool,
+
+    /// Tags of the links the strict mode applies to, the others are linked lazily
+    #[arg(long = "strict-tags", global = true, value_delimiter = ',', value_name = "TAGS")]
+    pub strict_tags: Vec<String>,
+
+    /// Home directory used to expand `~/` in targets
+    #[arg(long = "home", global = true, value_name = "DIR")]
+    pub home: Option<path::PathBuf>,
+
+    /// Shows only links with these statuses, e.g. alien,todo,error
+    #[arg(
+        value_enum,
+        long = "only",
+        global = true,
+        value_delimiter = ',',
+        value_name = "STATUS",
+        ignore_case = true
+    )]
+    pub only: Vec<Status>,
+
+    /// Shows only problems and the summary
+    #[arg(short = 'q', long = "quiet", global = true)]
+    pub quiet: bool,
+
+    /// Groups links by their tag or by the top level directory of the source
+    #[arg(value_enum, long = "group-by", global = true)]
+    pub group_by: Option<Grouping>,
+
+    /// Lists matches of the wildcard sources left out by the ignore rules in the dry mode
+    #[arg(long = "show-ignored", global = true)]
+    pub show_ignored: bool,
+
+    /// Shows targets as a tree of directories
+    #[arg(long = "tree", global = true)]
+    pub tree: bool,
+
+    /// Colors of the output, `NO_COLOR` and `CLICOLOR_FORCE` are honored in the auto mode
+    #[arg(value_enum, long = "color", global = true, value_name = "WHEN", default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+
+    /// Syntax of the linkfile, taken from its extension by default
+    #[arg(value_enum, long = "format", global = true)]
+    pub format: Option<FormatArg>,
+
+    /// Directory every resolved target is placed under
+    #[arg(long = "destdir", global = true, value_name = "DIR")]
+    pub destdir: Option<path::PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Mode given explicitly or the default one of the command
+    pub fn mode(&self) -> Mode {
+        match (self.mode, &self.command) {
+            (Some(mode), _) => mode.into(),
+            (None, Some(Command::Watch { .. } | Command::Sync { .. })) => Mode::Lazy,
+            (None, _) => Mode::Dry,
+        }
+    }
+
+    pub fn linkage_options(&self) -> LinkageOptions {
+        LinkageOptions {
+            mode: self.mode(),
+            explicit_mode: self.mode.is_some(),
+            tags: Tags::from(self.tags.clone()),
+            allow_missing_sources: self.keep_going,
+            normalize_links: self.normalize,
+            allow_unsafe_sources: self.allow_unsafe_sources,
+            target_root: TargetRoot {
+                home: self.home.as_deref().map(absolute),
+                destdir: self.destdir.as_deref().map(absolute),
+            },
+            strictness: Strictness {
+                allow_linked: self.allow_linked,
+                tags: self.strict_tags.clone(),
+            },
+            // Comes from the state, it is not known before the linkfile is loaded
+            deployed: HashMap::new(),
+        }
+    }
+
+    pub fn report(&self, root: &path::Path) -> Report {
+        Report {
+            only: self.only.clone(),
+            quiet: self.quiet,
+            group_by: self.group_by,
+            root: root.to_path_buf(),
+            tree: self.tree,
+            home: self.home.as_deref().map(absolute).or_else(dirs::home_dir),
+            width: terminal_width(),
+        }
+    }
+
+    /// Whether the output is colored, see `printer::color_enabled`
+    pub fn colors(&self) -> bool {
+        printer::color_enabled(self.color, |name| env::var(name).ok(), io::stdout().is_terminal())
+    }
+
+    pub fn format(&self) -> Option<Format> {
+        self.format.map(Format::from)
+    }
+
+    /// Loads the linkfile in the format given by `--format` or its extension
+    pub fn load_dotfiles(&self, linkfile: &path::Path) -> Result<Dotfiles, Error> {
+        match self.format() {
+            Some(format) => Dotfiles::load_as(linkfile, format),
+            None => Dotfiles::load(linkfile),
+        }
+    }
+}
+
+/// Width of the terminal, unknown when the output is not a terminal
+fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size::terminal_size().map(|(terminal_size::Width(width), _)| usize::from(width))
+}
+
+fn absolute(dir: &path::Path) -> path::PathBuf {
+    path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Mode, Parser, Status};
+
+    #[test]
+    fn explicit_mode_wins_over_default_of_command() {
+        let mode = |args: &[&str]| Cli::parse_from([&["rinku"], args].concat()).mode();
+
+        assert_eq!(mode(&[]), Mode::Dry);
+        assert_eq!(mode(&["sync"]), Mode::Lazy);
+        assert_eq!(mode(&["watch"]), Mode::Lazy);
+        assert_eq!(mode(&["-m", "dry", "sync"]), Mode::Dry);
+        assert_eq!(mode(&["watch", "-m", "strict"]), Mode::Strict);
+    }
+
+    #[test]
+    fn only_accepts_known_statuses() {
+        let cli = Cli::parse_from(["rinku", "--only", "alien,NO_PARENT,error"]);
+        assert_eq!(cli.only, vec![Status::Alien, Status::NoParent, Status::Error]);
+
+        assert!(Cli::try_parse_from(["rinku", "--only", "alien,aliens"]).is_err());
+    }
 }
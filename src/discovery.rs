@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::linker::expand_dest;
+use crate::linkfile::LINKFILE_NAMES;
+
+/// Environment variable with the path of the linkfile
+pub const LINKFILE_VAR: &str = "RINKU_LINKFILE";
+
+/// User configuration, `$XDG_CONFIG_HOME/rinku/config.toml`
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Dotfiles directory used outside of any dotfiles directory
+    pub repository: Option<String>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Error> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(Error::Config(err)),
+        };
+
+        Ok(toml::from_str(&content)?)
+    }
+}
+
+/// Location of the user configuration
+pub fn config_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::home_dir()?.join(".config"),
+    };
+    Some(config_home.join("rinku").join("config.toml"))
+}
+
+/// Linkfile with one of the conventional names in the directory
+pub fn find_in(dir: &Path) -> Option<PathBuf> {
+    LINKFILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Walks up from the directory like git looking for `.git`
+pub fn find_upwards(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().find_map(find_in)
+}
+
+/// Finds the linkfile when it is not given explicitly
+///
+/// `RINKU_LINKFILE` comes first, then the linkfile of the dotfiles
+/// directory the current directory belongs to, then the repository from
+/// the user configuration.
+pub fn discover() -> Result<Option<PathBuf>, Error> {
+    if let Some(linkfile) = env::var_os(LINKFILE_VAR).filter(|linkfile| !linkfile.is_empty()) {
+        return Ok(Some(PathBuf::from(linkfile)));
+    }
+
+    if let Some(linkfile) = env::current_dir().ok().as_deref().and_then(find_upwards) {
+        return Ok(Some(linkfile));
+    }
+
+    let config = match config_path() {
+        Some(config_path) => Config::load(&config_path)?,
+        None => Config::default(),
+    };
+    Ok(config
+        .repository
+        .map(|repository| expand_dest(Path::new(&repository)))
+        .and_then(|repository| find_in(&repository)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_upwards, Config};
+    use std::fs;
+
+    #[test]
+    fn linkfile_is_found_in_ancestors() {
+        let temp = tempfile::tempdir().unwrap();
+        let nested = temp.path().join("dotfiles/nvim/lua");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(temp.path().join("dotfiles/rinku.toml"), "").unwrap();
+
+        assert_eq!(find_upwards(&nested), Some(temp.path().join("dotfiles/rinku.toml")));
+        assert_eq!(find_upwards(temp.path()), None);
+    }
+
+    #[test]
+    fn config_names_repository() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("config.toml");
+        fs::write(&path, "repository = \"~/dotfiles\"\n").unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.repository.as_deref(), Some("~/dotfiles"));
+        assert_eq!(Config::load(&temp.path().join("missing.toml")).unwrap(), Config::default());
+    }
+}
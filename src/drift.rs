@@ -0,0 +1,396 @@
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::filesystem::{FileSystem, NodeType};
+use crate::linker::{LinkResult, LinkState, LinkTask};
+use crate::plan::Action;
+use crate::secrets::Decryptor;
+use crate::state::{CopyRecord, State};
+
+/// Content of the deployed copies addressed by its hash
+///
+/// Content is kept only when `keep` is set, i.e. `[meta] merge_base`, as
+/// copies may hold decrypted secrets, otherwise the state has just the
+/// hashes. Files are readable only by the owner.
+pub struct BaseStore<'a> {
+    fs: &'a dyn FileSystem,
+    dir: PathBuf,
+    keep: bool,
+}
+
+impl<'a> BaseStore<'a> {
+    pub fn new(fs: &'a dyn FileSystem, dir: &Path, keep: bool) -> Self {
+        BaseStore { fs, dir: dir.to_path_buf(), keep }
+    }
+
+    /// Keeps the content if the store is enabled and returns its hash
+    pub fn save(&self, content: &[u8]) -> io::Result<String> {
+        let hash = hash(content);
+        if !self.keep {
+            return Ok(hash);
+        }
+
+        self.fs.create_dir_all(&self.dir)?;
+        match self.fs.create_file(&self.dir.join(&hash), content, 0o600) {
+            Err(err) if err.kind() != io::ErrorKind::AlreadyExists => Err(err),
+            _ => Ok(hash),
+        }
+    }
+
+    /// Content deployed with the hash, none if it is not kept
+    pub fn load(&self, hash: &str) -> io::Result<Option<Vec<u8>>> {
+        if !self.keep {
+            return Ok(None);
+        }
+
+        match self.fs.read(&self.dir.join(hash)) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Removes content no copy of the state refers to, everything when the
+    /// store is disabled
+    pub fn collect_garbage(&self, state: &State) -> io::Result<()> {
+        let entries = match self.fs.read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+
+        let hashes: HashSet<&str> = match self.keep {
+            true => state.copies.iter().map(|copy| copy.hash.as_str()).collect(),
+            false => HashSet::new(),
+        };
+        for entry in entries {
+            let is_referenced = entry
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| hashes.contains(name));
+            if !is_referenced {
+                self.fs.remove_all(&entry)?;
+            }
+        }
+
+        if hashes.is_empty() {
+            self.fs.remove_dir(&self.dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Hex encoded SHA-256 of the content
+pub fn hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Result of the three-way merge of the local and upstream changes
+#[derive(Debug, Eq, PartialEq)]
+pub enum Merge {
+    Clean(Vec<u8>),
+    /// Merged content with conflict markers
+    Conflict(Vec<u8>),
+}
+
+/// How the copy relates to the deployed content and the current source
+#[derive(Debug)]
+pub enum DriftStatus {
+    Unchanged,
+    LocallyModified,
+    UpstreamChanged,
+    /// Both the copy and the source changed since the deployment, merged
+    /// only when the deployed content is kept
+    Diverged(Option<Merge>),
+    /// Copy was not deployed by rinku or before drift was tracked
+    Untracked,
+    Missing,
+    IoError(io::Error),
+}
+
+#[derive(Debug)]
+pub struct DriftState {
+    pub task: LinkTask,
+    pub status: DriftStatus,
+}
+
+/// Content the copy gets from its source
+fn render(fs: &dyn FileSystem, decryptor: &dyn Decryptor, link_task: &LinkTask) -> io::Result<Vec<u8>> {
+    let content = fs.read(&link_task.source)?;
+    match link_task.encryption {
+        Some(encryption) => decryptor.decrypt(encryption, &content),
+        None => Ok(content),
+    }
+}
+
+/// Remembers content of the copies deployed by the linkage
+///
+/// Copies found in place are adopted only when nothing is known about them
+/// yet, otherwise local edits would become the new base. Content of the
+/// replaced copies is removed from the store.
+pub fn record_copies(
+    store: &BaseStore,
+    state: &mut State,
+    link_states: &[LinkState],
+) -> io::Result<()> {
+    for link_state in link_states {
+        let task = &link_state.task;
+        if task.encryption.is_none() {
+            continue;
+        }
+
+        let deployed = link_state.actions.iter().any(|action| matches!(action, Action::Decrypt { .. }));
+        let adopted = matches!(link_state.result, LinkResult::Existed) && state.copy(&task.target).is_none();
+        if !deployed && !adopted {
+            continue;
+        }
+
+        let hash = store.save(&store.fs.read(&task.target)?)?;
+        state.record_copy(CopyRecord { source: task.source.clone(), target: task.target.clone(), hash });
+    }
+
+    store.collect_garbage(state)
+}
+
+/// Compares every copy with what was deployed and with its current source
+pub fn examine_drift(
+    fs: &dyn FileSystem,
+    decryptor: &dyn Decryptor,
+    store: &BaseStore,
+    link_tasks: Vec<LinkTask>,
+    state: &State,
+) -> Vec<DriftState> {
+    link_tasks
+        .into_iter()
+        .filter(|link_task| link_task.encryption.is_some())
+        .map(|task| {
+            let status = drift_status(fs, decryptor, store, &task, state)
+                .unwrap_or_else(DriftStatus::IoError);
+            DriftState { task, status }
+        })
+        .collect()
+}
+
+fn drift_status(
+    fs: &dyn FileSystem,
+    decryptor: &dyn Decryptor,
+    store: &BaseStore,
+    link_task: &LinkTask,
+    state: &State,
+) -> io::Result<DriftStatus> {
+    match fs.symlink_node_type(&link_task.target) {
+        Ok(NodeType::File) => {}
+        Ok(_) => return Ok(DriftStatus::Untracked),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(DriftStatus::Missing),
+        Err(err) => return Err(err),
+    }
+
+    let record = match state.copy(&link_task.target) {
+        Some(record) => record,
+        None => return Ok(DriftStatus::Untracked),
+    };
+
+    let local = fs.read(&link_task.target)?;
+    let upstream = render(fs, decryptor, link_task)?;
+    let is_local_modified = hash(&local) != record.hash;
+    let is_upstream_changed = hash(&upstream) != record.hash;
+
+    let status = match (is_local_modified, is_upstream_changed) {
+        (false, false) => DriftStatus::Unchanged,
+        (true, false) => DriftStatus::LocallyModified,
+        (false, true) => DriftStatus::UpstreamChanged,
+        // Same edit on both sides
+        (true, true) if local == upstream => DriftStatus::Unchanged,
+        (true, true) => DriftStatus::Diverged(store.load(&record.hash)?.map(|base| {
+            match diffy::merge_bytes(&base, &local, &upstream) {
+                Ok(merged) => Merge::Clean(merged),
+                Err(merged) => Merge::Conflict(merged),
+            }
+        })),
+    };
+
+    Ok(status)
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::linker::{apply_in, plan_in, LinkageOptions, LinkageResult, Mode, TargetRoot};
+    use crate::linkfile::{Dotfiles, Format};
+    use crate::filesystem::MemoryFileSystem;
+    use crate::secrets::FakeDecryptor;
+
+    const LINKFILE: &str = r#"
+        [[link]]
+        source = "netrc.age"
+        target = "/home/link/.netrc"
+        encryption = "age"
+    "#;
+
+    fn dotfiles() -> Dotfiles {
+        Dotfiles {
+            linkfile_path: PathBuf::from("/dotfiles/dotfiles.toml"),
+            root: PathBuf::from("/dotfiles"),
+            linkfile: toml::from_str(LINKFILE).unwrap(),
+            format: Format::Toml,
+        }
+    }
+
+    fn deploy(fs: &MemoryFileSystem, state: &mut State, content: &[u8]) {
+        deploy_with(fs, state, content, true)
+    }
+
+    fn deploy_with(fs: &MemoryFileSystem, state: &mut State, content: &[u8], keep: bool) {
+        let decryptor = FakeDecryptor::new(b"key");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(content));
+
+        let options = LinkageOptions { mode: Mode::Force, ..LinkageOptions::default() };
+        let plan = plan_in(fs, &dotfiles(), &options).unwrap();
+        match apply_in(fs, &decryptor, &dotfiles(), plan) {
+            LinkageResult::Completed(link_states, _) => {
+                let store = BaseStore::new(fs, Path::new("/state/base"), keep);
+                record_copies(&store, state, &link_states).unwrap();
+            }
+            result => panic!("Linkage was not completed: {:?}", result),
+        }
+    }
+
+    fn drift(fs: &MemoryFileSystem, state: &State) -> DriftStatus {
+        drift_with(fs, state, true)
+    }
+
+    fn drift_with(fs: &MemoryFileSystem, state: &State, keep: bool) -> DriftStatus {
+        let options = LinkageOptions::default();
+        let link_tasks = crate::linker::examine_in(fs, &dotfiles(), &options).unwrap();
+        let store = BaseStore::new(fs, Path::new("/state/base"), keep);
+        let decryptor = FakeDecryptor::new(b"key");
+
+        let mut drift_states = examine_drift(fs, &decryptor, &store, link_tasks, state);
+        assert_eq!(drift_states.len(), 1);
+        drift_states.remove(0).status
+    }
+
+    fn filesystem() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        fs.add_dir("/dotfiles");
+        fs.add_dir("/home/link");
+        fs
+    }
+
+    #[test]
+    fn drift_classification() {
+        let fs = filesystem();
+        let mut state = State::default();
+
+        fs.add_file_content("/dotfiles/netrc.age", b"");
+        assert!(matches!(drift(&fs, &state), DriftStatus::Missing));
+
+        deploy(&fs, &mut state, b"machine a\nlogin a\n");
+        assert_eq!(state.copies.len(), 1);
+        assert!(state.links.is_empty());
+        assert!(matches!(drift(&fs, &state), DriftStatus::Unchanged));
+
+        fs.add_file_content("/home/link/.netrc", b"machine a\nlogin b\n");
+        assert!(matches!(drift(&fs, &state), DriftStatus::LocallyModified));
+
+        fs.add_file_content("/home/link/.netrc", b"machine a\nlogin a\n");
+        let decryptor = FakeDecryptor::new(b"key");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(b"machine c\nlogin a\n"));
+        assert!(matches!(drift(&fs, &state), DriftStatus::UpstreamChanged));
+
+        assert!(matches!(drift(&fs, &State::default()), DriftStatus::Untracked));
+    }
+
+    #[test]
+    fn diverged_copies_are_merged() {
+        let fs = filesystem();
+        let mut state = State::default();
+        deploy(&fs, &mut state, b"machine a\nlogin a\npassword a\n");
+
+        let decryptor = FakeDecryptor::new(b"key");
+        fs.add_file_content("/home/link/.netrc", b"machine a\nlogin a\npassword b\n");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(b"machine c\nlogin a\npassword a\n"));
+        match drift(&fs, &state) {
+            DriftStatus::Diverged(merge) => {
+                assert_eq!(merge, Some(Merge::Clean(b"machine c\nlogin a\npassword b\n".to_vec())))
+            }
+            status => panic!("Unexpected status: {:?}", status),
+        }
+
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(b"machine a\nlogin a\npassword c\n"));
+        match drift(&fs, &state) {
+            DriftStatus::Diverged(Some(Merge::Conflict(merged))) => {
+                let merged = String::from_utf8(merged).unwrap();
+                assert!(merged.contains("<<<<<<<"), "{}", merged);
+                assert!(merged.contains("password b"), "{}", merged);
+                assert!(merged.contains("password c"), "{}", merged);
+            }
+            status => panic!("Unexpected status: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn only_hashes_are_kept_by_default() {
+        let fs = filesystem();
+        let mut state = State::default();
+        deploy_with(&fs, &mut state, b"password a\n", false);
+        assert_eq!(state.copies[0].hash, hash(b"password a\n"));
+        assert!(fs.read_dir(Path::new("/state/base")).is_err());
+
+        let decryptor = FakeDecryptor::new(b"key");
+        fs.add_file_content("/home/link/.netrc", b"password b\n");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(b"password c\n"));
+        assert!(matches!(drift_with(&fs, &state, false), DriftStatus::Diverged(None)));
+    }
+
+    #[test]
+    fn unreferenced_content_is_removed() {
+        let fs = filesystem();
+        let mut state = State::default();
+        deploy(&fs, &mut state, b"password a\n");
+        let base = Path::new("/state/base").join(hash(b"password a\n"));
+        assert_eq!(fs.read(&base).unwrap(), b"password a\n");
+
+        deploy(&fs, &mut state, b"password b\n");
+        assert!(fs.read(&base).is_err());
+        let entries = fs.read_dir(Path::new("/state/base")).unwrap();
+        assert_eq!(entries, vec![Path::new("/state/base").join(hash(b"password b\n"))]);
+
+        // Content kept before the store was disabled
+        BaseStore::new(&fs, Path::new("/state/base"), false).collect_garbage(&state).unwrap();
+        assert!(fs.read_dir(Path::new("/state/base")).is_err());
+
+        state.copies.clear();
+        deploy(&fs, &mut state, b"password c\n");
+        state.copies.clear();
+        BaseStore::new(&fs, Path::new("/state/base"), true).collect_garbage(&state).unwrap();
+        assert!(fs.read_dir(Path::new("/state/base")).is_err());
+    }
+
+    #[test]
+    fn alternate_root_leaves_base_store_untouched() {
+        let fs = filesystem();
+        let base_dir = crate::state::base_dir(&dotfiles().linkfile_path).unwrap();
+        let kept = base_dir.join(hash(b"password a\n"));
+        fs.add_file_content(&kept, b"password a\n");
+
+        let decryptor = FakeDecryptor::new(b"key");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor.encrypt(b"password b\n"));
+        let target_root = TargetRoot { destdir: Some(PathBuf::from("/image")), ..TargetRoot::default() };
+        let options = LinkageOptions { mode: Mode::Force, target_root, ..LinkageOptions::default() };
+        let plan = plan_in(&fs, &dotfiles(), &options).unwrap();
+        let mut state = State::default();
+        match apply_in(&fs, &decryptor, &dotfiles(), plan) {
+            LinkageResult::Completed(link_states, _) => {
+                state.record_linkage(&fs, &dotfiles(), &options.target_root, &link_states).unwrap();
+            }
+            result => panic!("Linkage was not completed: {:?}", result),
+        }
+
+        assert!(state.copies.is_empty());
+        assert_eq!(fs.read(&kept).unwrap(), b"password a\n");
+    }
+}
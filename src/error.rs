@@ -1,5 +1,4 @@
 use std::{fmt, io};
-use strum;
 use std::collections::HashMap;
 use std::path;
 
@@ -8,9 +7,27 @@ pub enum Error {
     BadLinkfilePath,
     BadLinkfile(io::Error),
     TomlParse(toml::de::Error),
+    YamlParse(String),
+    JsonParse(String),
     EnumParse(strum::ParseError),
-    LinkfileContentError(Vec<(path::PathBuf, io::Error)>),
-    TargetConflict(HashMap<path::PathBuf, Vec<path::PathBuf>>)
+    LinkfileContent(Vec<(path::PathBuf, io::Error)>),
+    TargetConflict(HashMap<path::PathBuf, Vec<path::PathBuf>>),
+    Backup(io::Error),
+    State(io::Error),
+    PlanFile(io::Error),
+    Watch(io::Error),
+    Git(io::Error),
+    Init(io::Error),
+    Config(io::Error),
+    /// Report could not be written
+    Output(io::Error),
+    /// Sources with unresolved merge conflicts
+    MergeConflicts(Vec<path::PathBuf>),
+    /// Sync can not be dry as it fast-forwards the repository
+    DrySync,
+    MissingLinkfile,
+    /// Paths writable by users other than root the linkage would rely on
+    UnsafeSources(Vec<path::PathBuf>),
 }
 
 impl From<toml::de::Error> for Error {
@@ -28,18 +45,46 @@ impl From<strum::ParseError> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Error::BadLinkfilePath => write!(f, "Path to linkfile is malformed\n"),
-            Error::BadLinkfile(err) => write!(f, "IO error during linkfile processing: {:?}\n", err),
+            Error::BadLinkfilePath => writeln!(f, "Path to linkfile is malformed"),
+            Error::BadLinkfile(err) => writeln!(f, "IO error during linkfile processing: {:?}", err),
             Error::TomlParse(err) => write!(f, "TomlParse error occured:\n{}\n", err),
-            Error::EnumParse(err) => write!(f, "EnumParse error occured: {:?}\n", err),
-            Error::LinkfileContentError(errs) => {
-                write!(f, "IO errors occured:\n")?;
+            Error::YamlParse(err) => write!(f, "YamlParse error occured:\n{}\n", err),
+            Error::JsonParse(err) => write!(f, "JsonParse error occured:\n{}\n", err),
+            Error::EnumParse(err) => writeln!(f, "EnumParse error occured: {:?}", err),
+            Error::LinkfileContent(errs) => {
+                writeln!(f, "IO errors occured:")?;
                 for (path, err) in errs {
-                    write!(f, "{}:\t{}\n", path.display(), err)?;
+                    writeln!(f, "{}:\t{}", path.display(), err)?;
+                }
+                Ok(())
+            }
+            Error::TargetConflict(err) => writeln!(f, "TargetConflict error occured: {:?}", err),
+            Error::Backup(err) => writeln!(f, "IO error during backups processing: {}", err),
+            Error::State(err) => writeln!(f, "IO error during state processing: {}", err),
+            Error::PlanFile(err) => writeln!(f, "IO error during plan processing: {}", err),
+            Error::Watch(err) => writeln!(f, "IO error during watching: {}", err),
+            Error::Git(err) => writeln!(f, "Git error occured: {}", err),
+            Error::Init(err) => writeln!(f, "IO error during initialization: {}", err),
+            Error::Config(err) => writeln!(f, "IO error during configuration processing: {}", err),
+            Error::Output(err) => writeln!(f, "IO error during output: {}", err),
+            Error::MergeConflicts(paths) => {
+                writeln!(f, "Refusing to link, sources have merge conflicts:")?;
+                for path in paths {
+                    writeln!(f, "{}", path.display())?;
+                }
+                Ok(())
+            }
+            Error::DrySync => {
+                writeln!(f, "Refusing to sync in the dry mode, use `git fetch` and `rinku plan` instead")
+            }
+            Error::MissingLinkfile => writeln!(f, "Linkfile is not specified and was not found"),
+            Error::UnsafeSources(paths) => {
+                writeln!(f, "Refusing to link as root, paths are writable by other users:")?;
+                for path in paths {
+                    writeln!(f, "{}", path.display())?;
                 }
                 Ok(())
             }
-            Error::TargetConflict(err) => write!(f, "TargetConflict error occured: {:?}\n", err),
         }
     }
 }
@@ -0,0 +1,787 @@
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use std::{fs, io, os};
+#[cfg(test)]
+use std::cell::{Cell, RefCell};
+#[cfg(test)]
+use std::collections::{BTreeMap, HashMap};
+#[cfg(test)]
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Limit of symlinks followed while resolving a single path
+#[cfg(test)]
+const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Kind of the filesystem node
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NodeType {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// Owner and permission bits of the node
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Stat {
+    pub uid: u32,
+    pub gid: u32,
+    pub mode: u32,
+}
+
+/// Owner and permission bits to apply to the created node, `None` keeps
+/// the value the node was created with
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Attributes {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub mode: Option<u32>,
+}
+
+impl Attributes {
+    pub fn is_empty(&self) -> bool {
+        self.uid.is_none() && self.gid.is_none() && self.mode.is_none()
+    }
+}
+
+/// Filesystem operations the linkage relies on
+///
+/// Every state check and mutation of the targets goes through this trait,
+/// [`RealFileSystem`] is used by rinku itself, [`MemoryFileSystem`] allows
+/// to exercise the linkage without touching the disk.
+pub trait FileSystem {
+    /// Type of the node, symlinks are followed
+    fn node_type(&self, path: &Path) -> io::Result<NodeType>;
+
+    /// Type of the node, symlinks are not followed
+    fn symlink_node_type(&self, path: &Path) -> io::Result<NodeType>;
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf>;
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Creates `target` symlink pointing to the `source`
+    fn symlink(&self, source: &Path, target: &Path) -> io::Result<()>;
+
+    fn remove_link(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes the directory, it has to be empty
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes the node, directories with everything inside, symlinks are
+    /// not followed
+    fn remove_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Owner and permissions of the node, symlinks are followed
+    fn stat(&self, path: &Path) -> io::Result<Stat>;
+
+    /// Changes owner of the node itself, symlinks are not followed
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()>;
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Entries of the directory sorted by name, symlinks are followed
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// Creates a new file with the permissions, occupied path is an error
+    fn create_file(&self, path: &Path, content: &[u8], mode: u32) -> io::Result<()>;
+
+    /// Modification time of the node, symlinks are followed
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+}
+
+/// Filesystem of the operating system
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn node_type(&self, path: &Path) -> io::Result<NodeType> {
+        fs::metadata(path).map(|metadata| node_type(&metadata))
+    }
+
+    fn symlink_node_type(&self, path: &Path) -> io::Result<NodeType> {
+        fs::symlink_metadata(path).map(|metadata| node_type(&metadata))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn symlink(&self, source: &Path, target: &Path) -> io::Result<()> {
+        platform_link(source, target)
+    }
+
+    fn remove_link(&self, path: &Path) -> io::Result<()> {
+        remove_link(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn remove_all(&self, path: &Path) -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            remove_link(path)
+        }
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        platform_stat(path)
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        platform_set_owner(path, uid, gid)
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        platform_set_mode(path, mode)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        Ok(entries)
+    }
+
+    fn create_file(&self, path: &Path, content: &[u8], mode: u32) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        platform_file_mode(&mut options, mode);
+        options.open(path)?.write_all(content)
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        fs::metadata(path)?.modified()
+    }
+}
+
+/// Resolves `.` and `..` components of the path without looking at the
+/// filesystem, `..` of a symlinked directory may end up elsewhere
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match (component, normalized.components().next_back()) {
+            (Component::CurDir, _) => {}
+            (Component::ParentDir, Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            // Parent of the root is the root itself
+            (Component::ParentDir, Some(Component::RootDir | Component::Prefix(_))) => {}
+            (component, _) => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn node_type(metadata: &fs::Metadata) -> NodeType {
+    if metadata.file_type().is_symlink() {
+        NodeType::Symlink
+    } else if metadata.is_dir() {
+        NodeType::Dir
+    } else {
+        NodeType::File
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn platform_link(source: &Path, dest: &Path) -> io::Result<()> {
+    os::unix::fs::symlink(source, dest)
+}
+
+#[cfg(target_family = "windows")]
+fn platform_link(source: &Path, dest: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        os::windows::fs::symlink_dir(source, dest)
+    } else {
+        os::windows::fs::symlink_file(source, dest)
+    }
+}
+
+#[cfg(target_family = "unix")]
+pub fn remove_link(link: &Path) -> io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(target_family = "windows")]
+pub fn remove_link(link: &Path) -> io::Result<()> {
+    // Directory symlinks on Windows have to be removed as directories
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))
+}
+
+#[cfg(target_family = "unix")]
+fn platform_stat(path: &Path) -> io::Result<Stat> {
+    use os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(path)?;
+    Ok(Stat { uid: metadata.uid(), gid: metadata.gid(), mode: metadata.mode() & 0o7777 })
+}
+
+#[cfg(target_family = "windows")]
+fn platform_stat(_path: &Path) -> io::Result<Stat> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "ownership is not supported"))
+}
+
+#[cfg(target_family = "unix")]
+fn platform_set_owner(path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+    os::unix::fs::lchown(path, uid, gid)
+}
+
+#[cfg(target_family = "windows")]
+fn platform_set_owner(_path: &Path, _uid: Option<u32>, _gid: Option<u32>) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "ownership is not supported"))
+}
+
+#[cfg(target_family = "unix")]
+fn platform_set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(target_family = "windows")]
+fn platform_set_mode(_path: &Path, _mode: u32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "permissions are not supported"))
+}
+
+#[cfg(target_family = "unix")]
+fn platform_file_mode(options: &mut fs::OpenOptions, mode: u32) {
+    use os::unix::fs::OpenOptionsExt;
+
+    options.mode(mode);
+}
+
+#[cfg(target_family = "windows")]
+fn platform_file_mode(_options: &mut fs::OpenOptions, _mode: u32) {}
+
+/// Node of the [`MemoryFileSystem`]
+#[cfg(test)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MemoryNode {
+    File,
+    Dir,
+    Symlink(PathBuf),
+}
+
+/// In-memory filesystem with unix semantics
+///
+/// Paths must be absolute, missing parents are created by the `add_*`
+/// methods. Operations on the paths registered with `fail` return the
+/// given error, which allows to simulate permission problems and alike.
+/// Directories registered with `mount` are separate devices, renames across
+/// them fail as on the real system. Nodes are owned by root unless `set_stat`
+/// says otherwise, modification times are ticks of the logical clock
+/// advanced by every change.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MemoryFileSystem {
+    nodes: RefCell<BTreeMap<PathBuf, MemoryNode>>,
+    details: RefCell<HashMap<PathBuf, Details>>,
+    clock: Cell<u64>,
+    failures: HashMap<PathBuf, io::ErrorKind>,
+    mounts: Vec<PathBuf>,
+}
+
+#[cfg(test)]
+#[derive(Clone, Debug, Default)]
+struct Details {
+    stat: Option<Stat>,
+    content: Vec<u8>,
+    modified: u64,
+}
+
+#[cfg(test)]
+impl Default for MemoryFileSystem {
+    fn default() -> Self {
+        MemoryFileSystem::new()
+    }
+}
+
+#[cfg(test)]
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(PathBuf::from("/"), MemoryNode::Dir);
+        MemoryFileSystem {
+            nodes: RefCell::new(nodes),
+            details: RefCell::new(HashMap::new()),
+            clock: Cell::new(0),
+            failures: HashMap::new(),
+            mounts: Vec::new(),
+        }
+    }
+
+    pub fn add_file(&self, path: impl AsRef<Path>) {
+        self.add(path.as_ref(), MemoryNode::File);
+    }
+
+    pub fn add_file_content(&self, path: impl AsRef<Path>, content: &[u8]) {
+        self.add(path.as_ref(), MemoryNode::File);
+        self.details_mut(path.as_ref(), |details| details.content = content.to_vec());
+    }
+
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        self.add(path.as_ref(), MemoryNode::Dir);
+    }
+
+    pub fn add_symlink(&self, path: impl AsRef<Path>, destination: impl AsRef<Path>) {
+        self.add(path.as_ref(), MemoryNode::Symlink(destination.as_ref().to_path_buf()));
+    }
+
+    /// Makes every operation on the path fail with the `kind`
+    pub fn fail(&mut self, path: impl AsRef<Path>, kind: io::ErrorKind) {
+        self.failures.insert(path.as_ref().to_path_buf(), kind);
+    }
+
+    /// Creates the directory on a device of its own
+    pub fn mount(&mut self, path: impl AsRef<Path>) {
+        self.add_dir(path.as_ref());
+        self.mounts.push(path.as_ref().to_path_buf());
+    }
+
+    /// Node stored at the path, symlinks are not followed
+    pub fn node(&self, path: impl AsRef<Path>) -> Option<MemoryNode> {
+        self.nodes.borrow().get(path.as_ref()).cloned()
+    }
+
+    /// Content of the file, symlinks are not followed
+    pub fn content(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        match self.node(path.as_ref())? {
+            MemoryNode::File => Some(self.details(path.as_ref()).content),
+            _ => None,
+        }
+    }
+
+    /// Marks the node as modified right now, symlinks are not followed
+    pub fn touch(&self, path: impl AsRef<Path>) {
+        let tick = self.tick();
+        self.details_mut(path.as_ref(), |details| details.modified = tick);
+    }
+
+    /// Overrides owner and permissions of the node, symlinks are not followed
+    pub fn set_stat(&self, path: impl AsRef<Path>, stat: Stat) {
+        self.details_mut(path.as_ref(), |details| details.stat = Some(stat));
+    }
+
+    /// Owner and permissions of the node, symlinks are not followed
+    pub fn node_stat(&self, path: impl AsRef<Path>) -> Option<Stat> {
+        let node = self.node(path.as_ref())?;
+        let stat = self.details(path.as_ref()).stat;
+        Some(stat.unwrap_or(Stat { uid: 0, gid: 0, mode: default_mode(&node) }))
+    }
+
+    fn add(&self, path: &Path, node: MemoryNode) {
+        let mut nodes = self.nodes.borrow_mut();
+        for ancestor in path.ancestors().skip(1) {
+            nodes.entry(ancestor.to_path_buf()).or_insert(MemoryNode::Dir);
+        }
+        nodes.insert(path.to_path_buf(), node);
+        drop(nodes);
+        self.details.borrow_mut().remove(path);
+        self.touch(path);
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.set(self.clock.get() + 1);
+        self.clock.get()
+    }
+
+    fn details(&self, path: &Path) -> Details {
+        self.details.borrow().get(path).cloned().unwrap_or_default()
+    }
+
+    fn details_mut(&self, path: &Path, update: impl FnOnce(&mut Details)) {
+        update(self.details.borrow_mut().entry(path.to_path_buf()).or_default());
+    }
+
+    /// Mount point of the device the resolved path is on, `None` for the root one
+    fn device(&self, path: &Path) -> Option<&Path> {
+        self.mounts
+            .iter()
+            .filter(|mount| path.starts_with(mount))
+            .max_by_key(|mount| mount.components().count())
+            .map(PathBuf::as_path)
+    }
+
+    fn check(&self, path: &Path) -> io::Result<()> {
+        match self.failures.get(path) {
+            Some(kind) => Err(io::Error::new(*kind, format!("simulated failure: {}", path.display()))),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves symlinks in all components of the path, the last one is
+    /// resolved only when `follow` is set
+    fn resolve(&self, path: &Path, follow: bool, depth: usize) -> io::Result<PathBuf> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("path is not absolute: {}", path.display()),
+            ));
+        }
+
+        let components: Vec<Component> = path.components().collect();
+        let mut resolved = PathBuf::new();
+
+        for (i, component) in components.iter().enumerate() {
+            let name = match component {
+                Component::Normal(name) => name,
+                Component::ParentDir => {
+                    resolved.pop();
+                    continue;
+                }
+                Component::CurDir => continue,
+                component => {
+                    resolved.push(component);
+                    continue;
+                }
+            };
+
+            let is_last = i + 1 == components.len();
+            let parent = resolved.clone();
+            resolved.push(name);
+
+            if is_last && !follow {
+                break;
+            }
+
+            let node = self.nodes.borrow().get(&resolved).cloned();
+            match node {
+                Some(MemoryNode::Symlink(destination)) => {
+                    if depth == 0 {
+                        return Err(io::Error::other(format!(
+                            "too many levels of symbolic links: {}",
+                            path.display()
+                        )));
+                    }
+                    resolved = self.resolve(&parent.join(destination), true, depth - 1)?;
+                }
+                Some(MemoryNode::File) if !is_last => return Err(not_found(path)),
+                None if !is_last => return Err(not_found(path)),
+                _ => {}
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    fn lookup(&self, path: &Path, follow: bool) -> io::Result<(PathBuf, MemoryNode)> {
+        let resolved = self.resolve(path, follow, MAX_SYMLINK_DEPTH)?;
+        let node = self.nodes.borrow().get(&resolved).cloned();
+        node.map(|node| (resolved, node)).ok_or_else(|| not_found(path))
+    }
+
+    /// Resolves path of the node to create, its parent has to be a directory
+    fn vacant(&self, path: &Path) -> io::Result<PathBuf> {
+        let resolved = self.resolve(path, false, MAX_SYMLINK_DEPTH)?;
+        let parent = resolved.parent().ok_or_else(|| not_found(path))?;
+        match self.nodes.borrow().get(parent) {
+            Some(MemoryNode::Dir) => Ok(resolved),
+            _ => Err(not_found(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl FileSystem for MemoryFileSystem {
+    fn node_type(&self, path: &Path) -> io::Result<NodeType> {
+        self.check(path)?;
+        self.lookup(path, true).map(|(_, node)| memory_node_type(&node))
+    }
+
+    fn symlink_node_type(&self, path: &Path) -> io::Result<NodeType> {
+        self.check(path)?;
+        self.lookup(path, false).map(|(_, node)| memory_node_type(&node))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.check(path)?;
+        match self.lookup(path, false)? {
+            (_, MemoryNode::Symlink(destination)) => Ok(destination),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a symlink: {}", path.display()),
+            )),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+        self.check(path)?;
+        self.lookup(path, true).map(|(resolved, _)| resolved)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        let ancestors: Vec<&Path> = path.ancestors().collect();
+        for ancestor in ancestors.into_iter().rev() {
+            match self.node_type(ancestor) {
+                Ok(NodeType::Dir) => {}
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("not a directory: {}", ancestor.display()),
+                    ))
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    let resolved = self.vacant(ancestor)?;
+                    self.add(&resolved, MemoryNode::Dir);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(from)?;
+        self.check(to)?;
+        let (from, _) = self.lookup(from, false)?;
+        let to = self.vacant(to)?;
+        if self.device(&from) != self.device(&to) {
+            return Err(io::Error::new(
+                io::ErrorKind::CrossesDevices,
+                format!("cross-device link: {}", to.display()),
+            ));
+        }
+        if let Some(MemoryNode::Dir) = self.nodes.borrow().get(&to) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("directory exists: {}", to.display()),
+            ));
+        }
+
+        let mut nodes = self.nodes.borrow_mut();
+        let mut details = self.details.borrow_mut();
+        let moved: Vec<PathBuf> = nodes.keys().filter(|key| key.starts_with(&from)).cloned().collect();
+        for key in moved {
+            let node = nodes.remove(&key).expect("Key collected from the map");
+            let suffix = key.strip_prefix(&from).expect("Key starts with the prefix");
+            if let Some(moved_details) = details.remove(&key) {
+                details.insert(to.join(suffix), moved_details);
+            }
+            nodes.insert(to.join(suffix), node);
+        }
+        Ok(())
+    }
+
+    fn symlink(&self, source: &Path, target: &Path) -> io::Result<()> {
+        self.check(target)?;
+        let resolved = self.vacant(target)?;
+        if self.nodes.borrow().contains_key(&resolved) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("file exists: {}", target.display()),
+            ));
+        }
+        self.add(&resolved, MemoryNode::Symlink(source.to_path_buf()));
+        Ok(())
+    }
+
+    fn remove_link(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        match self.lookup(path, false)? {
+            (_, MemoryNode::Dir) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("is a directory: {}", path.display()),
+            )),
+            (resolved, _) => {
+                self.nodes.borrow_mut().remove(&resolved);
+                self.details.borrow_mut().remove(&resolved);
+                Ok(())
+            }
+        }
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        let resolved = match self.lookup(path, false)? {
+            (resolved, MemoryNode::Dir) => resolved,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotADirectory,
+                    format!("not a directory: {}", path.display()),
+                ))
+            }
+        };
+        if self.nodes.borrow().keys().any(|key| key.parent() == Some(resolved.as_path())) {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                format!("directory not empty: {}", path.display()),
+            ));
+        }
+        self.nodes.borrow_mut().remove(&resolved);
+        self.details.borrow_mut().remove(&resolved);
+        Ok(())
+    }
+
+    fn remove_all(&self, path: &Path) -> io::Result<()> {
+        self.check(path)?;
+        let (resolved, _) = self.lookup(path, false)?;
+        self.nodes.borrow_mut().retain(|key, _| !key.starts_with(&resolved));
+        self.details.borrow_mut().retain(|key, _| !key.starts_with(&resolved));
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Stat> {
+        self.check(path)?;
+        let (resolved, _) = self.lookup(path, true)?;
+        Ok(self.node_stat(resolved).expect("Node is looked up"))
+    }
+
+    fn set_owner(&self, path: &Path, uid: Option<u32>, gid: Option<u32>) -> io::Result<()> {
+        self.check(path)?;
+        let (resolved, _) = self.lookup(path, false)?;
+        let stat = self.node_stat(&resolved).expect("Node is looked up");
+        self.set_stat(
+            resolved,
+            Stat { uid: uid.unwrap_or(stat.uid), gid: gid.unwrap_or(stat.gid), ..stat },
+        );
+        Ok(())
+    }
+
+    fn set_mode(&self, path: &Path, mode: u32) -> io::Result<()> {
+        self.check(path)?;
+        let (resolved, _) = self.lookup(path, true)?;
+        let stat = self.node_stat(&resolved).expect("Node is looked up");
+        self.set_stat(resolved, Stat { mode, ..stat });
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.check(path)?;
+        match self.lookup(path, true)? {
+            (resolved, MemoryNode::File) => Ok(self.details(&resolved).content),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a file: {}", path.display()),
+            )),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        self.check(path)?;
+        match self.lookup(path, true)? {
+            (resolved, MemoryNode::Dir) => Ok(self
+                .nodes
+                .borrow()
+                .keys()
+                .filter(|key| key.parent() == Some(resolved.as_path()))
+                .filter_map(|key| key.file_name())
+                .map(|name| path.join(name))
+                .collect()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a directory: {}", path.display()),
+            )),
+        }
+    }
+
+    fn create_file(&self, path: &Path, content: &[u8], mode: u32) -> io::Result<()> {
+        self.check(path)?;
+        let resolved = self.vacant(path)?;
+        if self.nodes.borrow().contains_key(&resolved) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("file exists: {}", path.display()),
+            ));
+        }
+        self.add_file_content(&resolved, content);
+        self.set_stat(&resolved, Stat { uid: 0, gid: 0, mode });
+        Ok(())
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        self.check(path)?;
+        let (resolved, _) = self.lookup(path, true)?;
+        Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(self.details(&resolved).modified))
+    }
+}
+
+#[cfg(test)]
+fn default_mode(node: &MemoryNode) -> u32 {
+    match node {
+        MemoryNode::File => 0o644,
+        MemoryNode::Dir => 0o755,
+        MemoryNode::Symlink(_) => 0o777,
+    }
+}
+
+#[cfg(test)]
+fn memory_node_type(node: &MemoryNode) -> NodeType {
+    match node {
+        MemoryNode::File => NodeType::File,
+        MemoryNode::Dir => NodeType::Dir,
+        MemoryNode::Symlink(_) => NodeType::Symlink,
+    }
+}
+
+#[cfg(test)]
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no such file or directory: {}", path.display()),
+    )
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::{FileSystem, MemoryFileSystem, MemoryNode, NodeType};
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn memory_symlink_resolution() {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/vimrc");
+        fs.add_symlink("/home/link/dotfiles", "../../dotfiles");
+        fs.add_symlink("/home/link/.vimrc", "dotfiles/vimrc");
+        fs.add_symlink("/home/link/.loop", ".loop");
+
+        let vimrc = Path::new("/home/link/.vimrc");
+        assert_eq!(fs.symlink_node_type(vimrc).unwrap(), NodeType::Symlink);
+        assert_eq!(fs.node_type(vimrc).unwrap(), NodeType::File);
+        assert_eq!(fs.canonicalize(vimrc).unwrap(), PathBuf::from("/dotfiles/vimrc"));
+
+        let err = fs.canonicalize(Path::new("/home/link/.loop")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn memory_rename_moves_children() {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/home/link/.config/nvim/init.lua");
+
+        fs.rename(Path::new("/home/link/.config"), Path::new("/home/link/.config.bak"))
+            .unwrap();
+
+        assert_eq!(fs.node("/home/link/.config"), None);
+        assert_eq!(fs.node("/home/link/.config.bak/nvim/init.lua"), Some(MemoryNode::File));
+    }
+}
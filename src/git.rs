@@ -0,0 +1,113 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::{io, process};
+
+/// Uncommitted change in the working tree
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    /// Absolute path of the changed file
+    pub path: PathBuf,
+    /// File has unresolved merge conflicts
+    pub conflicted: bool,
+}
+
+/// Runs the `git` executable found in `PATH` against the repository
+///
+/// Output of the failed commands is returned as the error message.
+fn git<I, S>(dir: &Path, args: I) -> io::Result<Vec<u8>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let output = process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(process::Stdio::null())
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("git is not available: {}", err)))?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+/// Top level directory of the repository the directory belongs to
+pub fn toplevel(dir: &Path) -> io::Result<PathBuf> {
+    let output = git(dir, ["rev-parse", "--show-toplevel"])?;
+    let toplevel = String::from_utf8_lossy(&output).trim_end().to_string();
+    Path::new(&toplevel).canonicalize()
+}
+
+/// Clones the repository into the new directory
+pub fn clone(url: &str, destination: &Path) -> io::Result<()> {
+    let cwd = std::env::current_dir()?;
+    let args = [OsStr::new("clone"), OsStr::new("--quiet"), OsStr::new(url), destination.as_os_str()];
+    git(&cwd, args).map(|_| ())
+}
+
+/// Uncommitted changes of the repository, untracked files included
+pub fn changes(toplevel: &Path) -> io::Result<Vec<Change>> {
+    let output = git(toplevel, ["status", "--porcelain=v1", "-z", "--untracked-files=all"])?;
+    Ok(parse_status(toplevel, &output))
+}
+
+/// Fast-forwards the current branch to its upstream
+pub fn pull(toplevel: &Path) -> io::Result<()> {
+    git(toplevel, ["pull", "--ff-only", "--quiet"]).map(|_| ())
+}
+
+/// Parses `git status --porcelain=v1 -z`
+///
+/// Entries are `XY path`, renames and copies are followed by the original
+/// path as a separate entry.
+fn parse_status(toplevel: &Path, output: &[u8]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut entries = output.split(|&byte| byte == 0).filter(|entry| !entry.is_empty());
+
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (status, path) = entry.split_at(3);
+        let path = String::from_utf8_lossy(path);
+        let conflicted = matches!(
+            &status[..2],
+            b"DD" | b"AU" | b"UD" | b"UA" | b"DU" | b"AA" | b"UU"
+        );
+        if matches!(status[0], b'R' | b'C') {
+            entries.next();
+        }
+        changes.push(Change { path: toplevel.join(path.as_ref()), conflicted });
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_status, Change};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn status_entries() {
+        let output = b" M vimrc\0UU nvim/init.lua\0R  zshrc\0bashrc\0?? new file\0";
+
+        let changes = parse_status(Path::new("/dotfiles"), output);
+
+        let change = |path: &str, conflicted| Change { path: PathBuf::from(path), conflicted };
+        assert_eq!(
+            changes,
+            vec![
+                change("/dotfiles/vimrc", false),
+                change("/dotfiles/nvim/init.lua", true),
+                change("/dotfiles/zshrc", false),
+                change("/dotfiles/new file", false),
+            ]
+        );
+    }
+}
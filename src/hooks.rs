@@ -0,0 +1,128 @@
+use std::ffi::OsString;
+use std::{io, path, process};
+
+use strum::Display;
+
+use crate::linker::Mode;
+use crate::linkfile::Hooks;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum HookKind {
+    PreLink,
+    PostLink,
+    PostUnlink,
+}
+
+#[derive(Debug)]
+pub enum HookStatus {
+    /// Hook of the dry run, it is only reported
+    Planned,
+    Succeeded,
+    Failed(process::ExitStatus),
+    IoError(io::Error),
+}
+
+#[derive(Debug)]
+pub struct HookRun {
+    pub kind: HookKind,
+    pub command: String,
+    pub status: HookStatus,
+}
+
+impl HookRun {
+    pub fn is_failure(&self) -> bool {
+        !matches!(self.status, HookStatus::Planned | HookStatus::Succeeded)
+    }
+}
+
+impl Hooks {
+    pub fn command(&self, kind: HookKind) -> Option<&str> {
+        match kind {
+            HookKind::PreLink => self.pre_link.as_deref(),
+            HookKind::PostLink => self.post_link.as_deref(),
+            HookKind::PostUnlink => self.post_unlink.as_deref(),
+        }
+    }
+}
+
+/// Executes hooks of a single rinku run
+///
+/// Every hook is started through the platform shell with the linkfile
+/// directory as working directory and receives `RINKU_HOOK`, `RINKU_MODE`
+/// and `RINKU_ROOT` variables. Hooks of a particular link additionally
+/// receive `RINKU_SOURCE` and `RINKU_TARGET`. Nothing is started in the dry
+/// mode, the hooks are reported as planned.
+pub struct HookRunner<'a> {
+    root: &'a path::Path,
+    mode: Mode,
+    hooks: &'a Hooks,
+}
+
+impl<'a> HookRunner<'a> {
+    pub fn new(root: &'a path::Path, mode: Mode, hooks: &'a Hooks) -> Self {
+        HookRunner { root, mode, hooks }
+    }
+
+    /// Runs hook from the `[meta]` section
+    pub fn run_for_linkage(&self, kind: HookKind) -> Option<HookRun> {
+        let command = self.hooks.command(kind)?;
+        Some(self.run(kind, command, vec![]))
+    }
+
+    /// Runs hook of a particular link
+    pub fn run_for_link(
+        &self,
+        hooks: &Hooks,
+        kind: HookKind,
+        source: &path::Path,
+        target: &path::Path,
+    ) -> Option<HookRun> {
+        let command = hooks.command(kind)?;
+        let vars = vec![
+            ("RINKU_SOURCE", source.as_os_str().to_owned()),
+            ("RINKU_TARGET", target.as_os_str().to_owned()),
+        ];
+        Some(self.run(kind, command, vars))
+    }
+
+    fn run(&self, kind: HookKind, command: &str, vars: Vec<(&str, OsString)>) -> HookRun {
+        if let Mode::Dry = self.mode {
+            return HookRun { kind, command: command.to_string(), status: HookStatus::Planned };
+        }
+
+        let status = shell_command(command)
+            .current_dir(self.root)
+            .env("RINKU_HOOK", kind.to_string())
+            .env("RINKU_MODE", self.mode.to_string())
+            .env("RINKU_ROOT", self.root)
+            .envs(vars)
+            .status();
+
+        let status = match status {
+            Ok(status) if status.success() => HookStatus::Succeeded,
+            Ok(status) => HookStatus::Failed(status),
+            Err(err) => HookStatus::IoError(err),
+        };
+
+        HookRun {
+            kind,
+            command: command.to_string(),
+            status,
+        }
+    }
+}
+
+#[cfg(target_family = "unix")]
+fn shell_command(command: &str) -> process::Command {
+    let mut shell = process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(target_family = "windows")]
+fn shell_command(command: &str) -> process::Command {
+    let mut shell = process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
@@ -0,0 +1,368 @@
+use std::fmt;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::filesystem::{FileSystem, NodeType};
+
+/// Name of the files with ignore rules, gitignore syntax
+pub const IGNORE_FILE: &str = ".rinkuignore";
+
+/// Where the rule comes from
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    /// `[meta] ignore` of the linkfile
+    Meta,
+    /// Line of the ignore file, relative to the linkfile directory
+    File { path: PathBuf, line: usize },
+    /// `exclude` of the link
+    Exclude,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Meta => write!(f, "meta ignore"),
+            Origin::File { path, line } => write!(f, "{}:{}", path.display(), line),
+            Origin::Exclude => write!(f, "exclude"),
+        }
+    }
+}
+
+/// Single pattern in gitignore syntax
+///
+/// Patterns with a slash are anchored to the directory of the rule, the
+/// others match names at any depth. Trailing slash limits the pattern to
+/// directories, `!` re-includes what earlier rules ignored.
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub pattern: String,
+    pub origin: Origin,
+    /// Directory of the rule relative to the linkfile directory
+    base: PathBuf,
+    matcher: Gitignore,
+}
+
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.origin == other.origin && self.base == other.base
+    }
+}
+
+impl Eq for Rule {}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.origin, self.pattern)
+    }
+}
+
+impl Rule {
+    /// Blank lines, comments and malformed patterns give no rule
+    pub fn parse(line: &str, base: &Path, origin: Origin) -> Option<Rule> {
+        let mut builder = GitignoreBuilder::new(".");
+        builder.add_line(None, line).ok()?;
+        let matcher = builder.build().ok().filter(|matcher| !matcher.is_empty())?;
+
+        Some(Rule {
+            pattern: line.trim_end().to_string(),
+            origin,
+            base: base.to_path_buf(),
+            matcher,
+        })
+    }
+
+    /// Whether the rule applies to the path relative to the linkfile
+    /// directory, either ignoring or re-including it
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        match path.strip_prefix(&self.base) {
+            Ok(relative) if relative != Path::new("") => !self.matcher.matched(relative, is_dir).is_none(),
+            _ => false,
+        }
+    }
+
+    fn is_negated(&self) -> bool {
+        self.matcher.num_whitelists() > 0
+    }
+}
+
+/// Rule deciding that the path is ignored, the last matching rule wins
+fn decide<'a>(rules: impl Iterator<Item = &'a Rule>, path: &Path, is_dir: bool) -> Option<&'a Rule> {
+    rules
+        .filter(|rule| rule.matches(path, is_dir))
+        .last()
+        .filter(|rule| !rule.is_negated())
+}
+
+/// Entry left out by an ignore rule
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ignored {
+    pub source: PathBuf,
+    pub rule: Rule,
+}
+
+/// Sources a glob source stands for
+#[derive(Debug, Default)]
+pub struct Expansion {
+    /// Directory before the first component with wildcards, targets of the
+    /// matches mirror their paths relative to it
+    pub base: PathBuf,
+    pub matches: Vec<PathBuf>,
+    pub ignored: Vec<Ignored>,
+}
+
+/// Source with `*`, `?` or `[...]` in any of its components
+pub fn is_glob(source: &str) -> bool {
+    source.contains(['*', '?', '['])
+}
+
+/// Finds entries matching the glob source
+///
+/// `**` matches any number of directories, trailing `**` matches only
+/// files, so the directories below are created instead of being linked as
+/// a whole. `[meta] ignore` rules come first, then ignore files from the
+/// linkfile directory down to the entry, then `exclude` of the link.
+pub fn expand(
+    fs: &dyn FileSystem,
+    root: &Path,
+    source: &str,
+    exclude: &[String],
+    meta_ignore: &[String],
+) -> io::Result<Expansion> {
+    let components = segments_of(Path::new(source));
+    let literal = components.iter().take_while(|component| !is_glob(component)).count();
+    let (prefix, pattern) = components.split_at(literal);
+    let prefix: PathBuf = prefix.iter().collect();
+    let base = root.join(&prefix);
+    let pattern = pattern.iter().map(|segment| Segment::parse(segment)).collect::<io::Result<Vec<_>>>()?;
+
+    if fs.node_type(&base)? != NodeType::Dir {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("not a directory: {}", base.display()),
+        ));
+    }
+
+    let meta_rules: Vec<Rule> = meta_ignore
+        .iter()
+        .filter_map(|pattern| Rule::parse(pattern, Path::new(""), Origin::Meta))
+        .collect();
+    let exclude_rules: Vec<Rule> = exclude
+        .iter()
+        .filter_map(|pattern| Rule::parse(pattern, &prefix, Origin::Exclude))
+        .collect();
+
+    let mut file_rules = Vec::new();
+    let dirs: Vec<&Path> = prefix.ancestors().collect();
+    for dir in dirs.into_iter().rev() {
+        file_rules.extend(read_rules(fs, root, dir)?);
+    }
+
+    let mut walk = Walk {
+        fs,
+        root,
+        base: &base,
+        pattern: &pattern,
+        meta_rules: &meta_rules,
+        exclude_rules: &exclude_rules,
+        expansion: Expansion { base: base.clone(), ..Expansion::default() },
+    };
+    walk.visit(&base, &prefix, &file_rules)?;
+
+    Ok(walk.expansion)
+}
+
+struct Walk<'a> {
+    fs: &'a dyn FileSystem,
+    root: &'a Path,
+    base: &'a Path,
+    pattern: &'a [Segment],
+    meta_rules: &'a [Rule],
+    exclude_rules: &'a [Rule],
+    expansion: Expansion,
+}
+
+impl Walk<'_> {
+    fn visit(&mut self, dir: &Path, relative_dir: &Path, file_rules: &[Rule]) -> io::Result<()> {
+        for entry in self.fs.read_dir(dir)? {
+            let name = match entry.file_name() {
+                Some(name) if name != IGNORE_FILE => name,
+                _ => continue,
+            };
+            let relative = relative_dir.join(name);
+            let from_base = segments_of(entry.strip_prefix(self.base).expect("Entry is below the base"));
+            let is_dir = self.fs.node_type(&entry).is_ok_and(|node_type| node_type == NodeType::Dir);
+
+            let is_trailing_any = matches!(self.pattern.last(), Some(Segment::AnyDirectories));
+            let is_match = match_segments(self.pattern, &from_base, false) && !(is_dir && is_trailing_any);
+            let is_descended = is_dir && !is_match && match_segments(self.pattern, &from_base, true);
+            if !is_match && !is_descended {
+                continue;
+            }
+
+            let rules = self.meta_rules.iter().chain(file_rules).chain(self.exclude_rules);
+            if let Some(rule) = decide(rules, &relative, is_dir) {
+                self.expansion.ignored.push(Ignored { source: entry, rule: rule.clone() });
+                continue;
+            }
+
+            if is_match {
+                self.expansion.matches.push(entry);
+            } else {
+                let mut rules = file_rules.to_vec();
+                rules.extend(read_rules(self.fs, self.root, &relative)?);
+                self.visit(&entry, &relative, &rules)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Rules of the ignore file in the directory relative to the linkfile one
+fn read_rules(fs: &dyn FileSystem, root: &Path, dir: &Path) -> io::Result<Vec<Rule>> {
+    let path = dir.join(IGNORE_FILE);
+    let content = match fs.read(&root.join(&path)) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    Ok(String::from_utf8_lossy(&content)
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            Rule::parse(line, dir, Origin::File { path: path.clone(), line: index + 1 })
+        })
+        .collect())
+}
+
+fn segments_of(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Component of the glob source
+enum Segment {
+    /// `**`
+    AnyDirectories,
+    Name(GlobMatcher),
+}
+
+impl Segment {
+    fn parse(segment: &str) -> io::Result<Segment> {
+        if segment == "**" {
+            return Ok(Segment::AnyDirectories);
+        }
+
+        let glob = GlobBuilder::new(segment)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(Segment::Name(glob.compile_matcher()))
+    }
+}
+
+/// Matches the path against the pattern component by component
+///
+/// With `partial` the path only has to be a beginning of a matching path.
+fn match_segments(pattern: &[Segment], path: &[String], partial: bool) -> bool {
+    match (pattern.first(), path.first()) {
+        (_, None) => partial || pattern.is_empty(),
+        (None, Some(_)) => false,
+        // Trailing `**` matches everything inside, but not the directory itself
+        (Some(Segment::AnyDirectories), Some(_)) if pattern.len() == 1 => true,
+        (Some(Segment::AnyDirectories), Some(_)) => {
+            match_segments(&pattern[1..], path, partial) || match_segments(pattern, &path[1..], partial)
+        }
+        (Some(Segment::Name(matcher)), Some(name)) => {
+            matcher.is_match(name) && match_segments(&pattern[1..], &path[1..], partial)
+        }
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::filesystem::MemoryFileSystem;
+
+    #[test]
+    fn wildcards() {
+        let matches = |pattern, name: &str| match Segment::parse(pattern).unwrap() {
+            Segment::Name(matcher) => matcher.is_match(name),
+            Segment::AnyDirectories => unreachable!(),
+        };
+
+        assert!(matches("*.swp", ".init.lua.swp"));
+        assert!(matches("README*", "README.md"));
+        assert!(!matches("README*", "readme.md"));
+        assert!(matches("[Rr]eadme.?d", "readme.md"));
+        assert!(matches("*~", "init.lua~"));
+        assert!(!matches("[!a-c]*", "bashrc"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(Segment::parse("[a").is_err());
+    }
+
+    #[test]
+    fn rules_follow_gitignore() {
+        let rule = |pattern| Rule::parse(pattern, Path::new("bin"), Origin::Exclude).unwrap();
+        let path = Path::new;
+
+        assert!(rule("README*").matches(path("bin/tools/README.md"), false));
+        assert!(!rule("README*").matches(path("README.md"), false));
+        assert!(rule("/tools/*.md").matches(path("bin/tools/README.md"), false));
+        assert!(!rule("/*.md").matches(path("bin/tools/README.md"), false));
+        assert!(!rule("cache/").matches(path("bin/cache"), false));
+        assert!(rule("**/cache/").matches(path("bin/a/b/cache"), true));
+        assert!(Rule::parse("# comment", Path::new(""), Origin::Meta).is_none());
+        assert!(Rule::parse("  ", Path::new(""), Origin::Meta).is_none());
+
+        let rules = [rule("*.md"), rule("!CHANGES.md")];
+        assert_eq!(decide(rules.iter(), path("bin/README.md"), false), Some(&rules[0]));
+        assert_eq!(decide(rules.iter(), path("bin/CHANGES.md"), false), None);
+    }
+
+    #[test]
+    fn glob_sources_skip_ignored_entries() {
+        let fs = MemoryFileSystem::new();
+        fs.add_file_content("/dotfiles/.rinkuignore", b"# editors\n*.swp\n");
+        fs.add_file("/dotfiles/bin/backup");
+        fs.add_file("/dotfiles/bin/.backup.swp");
+        fs.add_file("/dotfiles/bin/README.md");
+        fs.add_file("/dotfiles/bin/.DS_Store");
+        fs.add_file("/dotfiles/nvim/init.lua");
+        fs.add_file("/dotfiles/nvim/lua/plugins.lua");
+        fs.add_file_content("/dotfiles/nvim/lua/.rinkuignore", b"plugins.lua\n");
+
+        let meta = [".DS_Store".to_string()];
+        let expansion = expand(&fs, Path::new("/dotfiles"), "bin/*", &["README*".to_string()], &meta).unwrap();
+        assert_eq!(expansion.base, PathBuf::from("/dotfiles/bin"));
+        assert_eq!(expansion.matches, vec![PathBuf::from("/dotfiles/bin/backup")]);
+
+        let ignored: Vec<String> = expansion
+            .ignored
+            .iter()
+            .map(|ignored| format!("{} {}", ignored.source.display(), ignored.rule))
+            .collect();
+        assert_eq!(
+            ignored,
+            vec![
+                "/dotfiles/bin/.DS_Store meta ignore: .DS_Store",
+                "/dotfiles/bin/.backup.swp .rinkuignore:2: *.swp",
+                "/dotfiles/bin/README.md exclude: README*",
+            ]
+        );
+
+        let expansion = expand(&fs, Path::new("/dotfiles"), "nvim/**", &[], &[]).unwrap();
+        assert_eq!(expansion.matches, vec![PathBuf::from("/dotfiles/nvim/init.lua")]);
+        assert_eq!(expansion.ignored[0].rule.to_string(), "nvim/lua/.rinkuignore:1: plugins.lua");
+    }
+}
@@ -0,0 +1,54 @@
+//! *rinku* ensures that required links exist and point to correct
+//! files/dirs according to the provided linkfile.
+//!
+//! The linkage is split into two steps: [`linker::plan`] examines the state
+//! of every target and decides which actions bring it in line with the
+//! [`linker::Mode`], [`linker::apply`] executes the resulting [`plan::Plan`].
+//!
+//! ```no_run
+//! use rinku::linker::{self, LinkageOptions, LinkageResult, Mode, Tags};
+//! use rinku::linkfile::Dotfiles;
+//!
+//! let dotfiles = Dotfiles::load("dotfiles.toml".as_ref())?;
+//! let options = LinkageOptions {
+//!     mode: Mode::Lazy,
+//!     tags: Tags::new(["work"]),
+//!     ..LinkageOptions::default()
+//! };
+//!
+//! let plan = linker::plan(&dotfiles, &options)?;
+//! if let LinkageResult::Completed(link_states, _) = linker::apply(&dotfiles, plan) {
+//!     for link_state in link_states {
+//!         println!("{}: {:?}", link_state.task.target.display(), link_state.result);
+//!     }
+//! }
+//! # Ok::<(), rinku::Error>(())
+//! ```
+
+mod app;
+mod backup;
+mod bootstrap;
+mod cli;
+mod discovery;
+mod drift;
+mod error;
+mod filesystem;
+mod git;
+mod hooks;
+mod ignore;
+pub mod linker;
+pub mod linkfile;
+pub mod plan;
+mod printer;
+mod prune;
+mod secrets;
+mod state;
+mod sync;
+mod users;
+mod watch;
+
+pub use crate::error::Error;
+
+// Entry point of the executable, not a part of the library API
+#[doc(hidden)]
+pub use crate::app::run;
@@ -1,36 +1,377 @@
 pub use crate::error::Error;
+pub use crate::filesystem::Attributes;
+pub use crate::hooks::{HookKind, HookRun, HookStatus};
 
 use std::cmp::Ordering;
-use std::fs::Metadata;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::str::FromStr;
-use std::{env, fs, io, os, path};
+use std::{env, fmt, io, path};
 use std::collections::HashMap;
 
-use crate::cli::Mode;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::backup::{mirror, BackupPolicy};
+use crate::drift;
+use crate::filesystem::{normalize, FileSystem, NodeType, RealFileSystem};
+use crate::hooks::HookRunner;
+use crate::ignore::{self, Ignored};
 use crate::linkfile::*;
+use crate::plan::{Action, Plan, PlannedLink};
+use crate::secrets::{CommandDecryptor, Decryptor};
+use crate::users;
+
+/// How existing targets are treated during the linkage
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Only prints status of the targets
+    Dry,
+
+    /// Performs linking only when no single target exists
+    Strict,
+
+    /// Fills missing targets, ignores existing one
+    Lazy,
+
+    /// Overrides all targets moving old versions to backups
+    Force,
+}
+
+/// Tags explicitly requested for the linkage
+///
+/// Links with a tag are enabled only when the tag is active. When no tags
+/// are given `[meta] default_tags` of the linkfile are used instead.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    pub fn new<I, S>(tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Tags(tags.into_iter().map(Into::into).collect())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, tag: &str) -> bool {
+        self.0.iter().any(|t| t == tag)
+    }
+
+    fn or_default<'a>(&'a self, meta: &'a Meta) -> &'a [String] {
+        if self.is_empty() {
+            &meta.default_tags
+        } else {
+            &self.0
+        }
+    }
+}
+
+impl From<Vec<String>> for Tags {
+    fn from(tags: Vec<String>) -> Self {
+        Tags(tags)
+    }
+}
+
+/// Parameters of `examine` and `plan`
+#[derive(Clone, Debug)]
+pub struct LinkageOptions {
+    pub mode: Mode,
+    /// Mode was given explicitly instead of being the default of the
+    /// command, see `ConflictPolicy::resolve`
+    pub explicit_mode: bool,
+    pub tags: Tags,
+    /// Report links with missing sources as `TargetState::SourceMissing`
+    /// instead of failing the whole linkage
+    pub allow_missing_sources: bool,
+    /// Relink `TargetState::LinkedIndirect` targets straight to the source
+    pub normalize_links: bool,
+    /// Link as root even when sources are writable by other users
+    pub allow_unsafe_sources: bool,
+    pub target_root: TargetRoot,
+    pub strictness: Strictness,
+    /// SHA-256 of the copies deployed by the previous runs by their targets,
+    /// see `State::deployed`
+    pub deployed: HashMap<path::PathBuf, String>,
+}
+
+impl Default for LinkageOptions {
+    fn default() -> Self {
+        LinkageOptions {
+            mode: Mode::Dry,
+            explicit_mode: false,
+            tags: Tags::default(),
+            allow_missing_sources: false,
+            normalize_links: false,
+            allow_unsafe_sources: false,
+            target_root: TargetRoot::default(),
+            strictness: Strictness::default(),
+            deployed: HashMap::new(),
+        }
+    }
+}
+
+/// Which targets the strict mode requires to be absent
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Strictness {
+    /// Linked targets count as satisfied, only conflicts refuse the linkage
+    pub allow_linked: bool,
+    /// Tags of the links the strict mode applies to, the other links are
+    /// linked lazily, every link when empty
+    pub tags: Vec<String>,
+}
 
-#[derive(Debug, Clone)]
+impl Strictness {
+    /// Mode the link is actually linked in
+    pub fn mode_of(&self, link_task: &LinkTask, mode: Mode) -> Mode {
+        let is_in_scope = self.tags.is_empty()
+            || link_task.tag.as_ref().is_some_and(|tag| self.tags.contains(tag));
+        match mode {
+            Mode::Strict if !is_in_scope => Mode::Lazy,
+            mode => mode,
+        }
+    }
+}
+
+/// Alternate location of the targets
+///
+/// Allows to populate a home directory of another user or a system image
+/// and to try the linkfile out in a temporary directory.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TargetRoot {
+    /// Replaces home directory of the current user in `~/` targets
+    pub home: Option<PathBuf>,
+    /// Prefix of every resolved target, like `DESTDIR` of `make install`
+    pub destdir: Option<PathBuf>,
+}
+
+impl TargetRoot {
+    pub fn is_default(&self) -> bool {
+        self.home.is_none() && self.destdir.is_none()
+    }
+
+    /// Expands `~/` using the alternate home if any, `~user/` always
+    /// refers to the home from the user database
+    pub fn expand_home(&self, path: &Path) -> PathBuf {
+        match (&self.home, home_user(path)) {
+            (Some(home), Some("")) => replace_home(path, home),
+            _ => expand_dest(path),
+        }
+    }
+
+    /// Final location of the target from the linkfile
+    pub fn resolve(&self, target: &Path) -> PathBuf {
+        let target = self.expand_home(target);
+        match &self.destdir {
+            Some(destdir) => mirror(destdir, &target),
+            None => target,
+        }
+    }
+}
+
+/// Reason the linkage was refused before touching any target
+#[derive(Clone, Debug)]
+pub enum Precondition {
+    /// Targets the strict mode or `on_conflict = "fail"` require to be
+    /// absent exist, the tasks have their current states
+    TargetsExist(Vec<LinkTask>),
+}
+
+impl fmt::Display for Precondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Precondition::TargetsExist(link_tasks) => {
+                let targets = if link_tasks.len() == 1 { "target exists" } else { "targets exist" };
+                write!(f, "{} {}", link_tasks.len(), targets)
+            }
+        }
+    }
+}
+
+/// State of the target found by `examine`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Display, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum TargetState {
     Absent,
-    AlienNode(Metadata),
-    AlienLink(Metadata),
-    Linked(Metadata),
+    AlienNode,
+    AlienLink,
+    DanglingLink,
+    Linked,
+    /// Link resolves to the source only through intermediate symlinks
+    LinkedIndirect,
+    /// Decrypted copy is older than the encrypted source
+    Outdated,
+    /// Decrypted copy was edited since it was deployed
+    Modified,
+    SourceMissing,
 }
 
-#[derive(Debug, Clone)]
+/// Single `source -> target` link of the linkfile
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LinkTask {
     pub source: path::PathBuf,
-    pub source_metadata: fs::Metadata,
     pub target: path::PathBuf,
     pub target_state: TargetState,
+    /// Tag of the link in the linkfile
+    #[serde(default)]
+    pub tag: Option<String>,
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub attributes: Attributes,
+    #[serde(default)]
+    pub parents: ParentPolicy,
+    /// Target is a decrypted copy of the source instead of the symlink
+    #[serde(default)]
+    pub encryption: Option<Encryption>,
+    /// Treatment of the occupied target set by the linkfile
+    #[serde(default)]
+    pub on_conflict: Option<ConflictPolicy>,
+    /// SHA-256 of the copy deployed by the previous run
+    #[serde(default)]
+    pub deployed: Option<String>,
 }
 
+impl LinkTask {
+    /// Target is occupied by something other than the link
+    pub fn is_conflict(&self) -> bool {
+        matches!(
+            self.target_state,
+            TargetState::AlienNode
+                | TargetState::AlienLink
+                | TargetState::DanglingLink
+                | TargetState::Outdated
+                | TargetState::Modified
+        )
+    }
+
+    /// Treatment of the occupied target in the mode, see
+    /// `ConflictPolicy::resolve`
+    pub fn conflict_policy(&self, mode: Mode, explicit_mode: bool) -> Option<ConflictPolicy> {
+        ConflictPolicy::resolve(self.on_conflict, ConflictPolicy::of_mode(mode, explicit_mode))
+    }
+}
+
+/// Where the conflict policy comes from, from the highest precedence to the
+/// lowest
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicySource {
+    Link,
+    /// Mode given with `-m`
+    ExplicitMode(Mode),
+    Meta,
+    /// Mode the command uses when none is given
+    DefaultMode(Mode),
+}
+
+impl PolicySource {
+    fn precedence(&self) -> u8 {
+        match self {
+            PolicySource::Link => 3,
+            PolicySource::ExplicitMode(_) => 2,
+            PolicySource::Meta => 1,
+            PolicySource::DefaultMode(_) => 0,
+        }
+    }
+
+    /// Sources the policy from this one takes precedence over
+    pub fn overridden(&self) -> &'static str {
+        match self {
+            PolicySource::Link => "any mode",
+            PolicySource::ExplicitMode(_) => "[meta]",
+            PolicySource::Meta => "the default mode, not the one given with -m",
+            PolicySource::DefaultMode(_) => "nothing",
+        }
+    }
+
+    pub fn is_mode(&self) -> bool {
+        matches!(self, PolicySource::ExplicitMode(_) | PolicySource::DefaultMode(_))
+    }
+}
+
+impl fmt::Display for PolicySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicySource::Link => write!(f, "link"),
+            PolicySource::ExplicitMode(mode) => write!(f, "{} mode", mode),
+            PolicySource::Meta => write!(f, "[meta]"),
+            PolicySource::DefaultMode(mode) => write!(f, "default {} mode", mode),
+        }
+    }
+}
+
+/// Treatment of the occupied target along with its origin
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConflictPolicy {
+    pub on_conflict: OnConflict,
+    pub source: PolicySource,
+}
+
+impl ConflictPolicy {
+    /// Policy of the mode, the dry mode touches nothing
+    pub fn of_mode(mode: Mode, explicit_mode: bool) -> Option<ConflictPolicy> {
+        let on_conflict = match mode {
+            Mode::Dry => return None,
+            Mode::Strict => OnConflict::Fail,
+            Mode::Lazy => OnConflict::Skip,
+            Mode::Force => OnConflict::Backup,
+        };
+        let source = match explicit_mode {
+            true => PolicySource::ExplicitMode(mode),
+            false => PolicySource::DefaultMode(mode),
+        };
+        Some(ConflictPolicy { on_conflict, source })
+    }
+
+    /// Policy of the linkfile or of the mode, whichever comes from the source
+    /// of higher precedence: the link, the mode given with `-m`, `[meta]`,
+    /// the default mode of the command
+    pub fn resolve(
+        linkfile: Option<ConflictPolicy>,
+        mode: Option<ConflictPolicy>,
+    ) -> Option<ConflictPolicy> {
+        linkfile.into_iter().chain(mode).max_by_key(|policy| policy.source.precedence())
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.on_conflict, self.source)
+    }
+}
+
+/// How missing parent directories of the target are treated
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ParentPolicy {
+    pub create: bool,
+    /// Applied to every created directory
+    pub attributes: Attributes,
+}
+
+impl Default for ParentPolicy {
+    fn default() -> Self {
+        ParentPolicy { create: true, attributes: Attributes::default() }
+    }
+}
+
+/// Outcome of the link task produced by `apply`
 #[derive(Debug)]
 pub enum LinkResult {
     Existed,
     Skipped,
     Success,
+    Normalized,
+    SourceMissing,
+    /// Target does not have the state the plan was made for anymore
+    StateChanged(TargetState),
+    /// Parent directory of the target is missing and may not be created
+    ParentMissing,
     IoError(io::Error),
 }
 
@@ -38,72 +379,246 @@ pub enum LinkResult {
 pub struct LinkState {
     pub task: LinkTask,
     pub result: LinkResult,
+    /// Actions that were executed successfully
+    pub actions: Vec<Action>,
+    pub hooks: Vec<HookRun>,
 }
 
+/// Outcome of the whole linkage, per-run hooks are reported separately
+/// from the hooks of particular links
 #[derive(Debug)]
 pub enum LinkageResult {
-    DryResult(Vec<LinkTask>),
-    PreconditionFailed(&'static str),
-    Completed(Vec<LinkState>),
+    DryResult(Vec<PlannedLink>, Hooks),
+    PreconditionFailed(Precondition),
+    HookFailed(HookRun),
+    Completed(Vec<LinkState>, Vec<HookRun>),
 }
 
-pub fn do_linkage(
-    mode: Mode,
-    root: &path::Path,
-    linkfile: &Linkfile,
-    tags: &Vec<String>
-) -> Result<LinkageResult, Error> {
-    let environment = Environment::from_str(env::consts::FAMILY)?;
+struct LinkageContext<'a> {
+    fs: &'a dyn FileSystem,
+    hook_runner: HookRunner<'a>,
+    backup_policy: BackupPolicy,
+    decryptor: &'a dyn Decryptor,
+}
 
-    let active_tags: &Vec<String> =
-        if tags.len() == 0 {
-            &linkfile.meta.default_tags
-        } else {
-            &tags
-        };
+/// Plans and applies the linkage in one go
+pub fn do_linkage(dotfiles: &Dotfiles, options: &LinkageOptions) -> Result<LinkageResult, Error> {
+    let plan = plan(dotfiles, options)?;
+    Ok(apply(dotfiles, plan))
+}
+
+/// Examines targets of all links enabled by the tags for the current platform
+///
+/// Missing sources abort the examination unless `allow_missing_sources` is
+/// set, then they are reported as `TargetState::SourceMissing` tasks.
+/// Policies of `[meta] on_conflict` are left out when the lazy or the strict
+/// mode is given explicitly.
+pub fn examine(dotfiles: &Dotfiles, options: &LinkageOptions) -> Result<Vec<LinkTask>, Error> {
+    examine_in(&RealFileSystem, dotfiles, options)
+}
 
-    let mut link_tasks = aggregate_link_tasks(environment, &root, &linkfile.links, &active_tags)?;
+/// Same as `examine`, but looks at the given filesystem
+pub(crate) fn examine_in(
+    fs: &dyn FileSystem,
+    dotfiles: &Dotfiles,
+    options: &LinkageOptions,
+) -> Result<Vec<LinkTask>, Error> {
+    let environment = Environment::from_str(env::consts::FAMILY)?;
+    let linkfile = &dotfiles.linkfile;
+
+    let mut link_tasks = aggregate_link_tasks(
+        fs,
+        environment,
+        &dotfiles.root,
+        linkfile,
+        options.tags.or_default(&linkfile.meta),
+        options,
+    )?;
     link_tasks.sort_by(compare_link_tasks);
+    Ok(link_tasks)
+}
+
+/// Matches of the wildcard sources of the enabled links left out by the
+/// ignore rules
+///
+/// Sources that may not be read are skipped, `examine` reports them.
+pub(crate) fn find_ignored(dotfiles: &Dotfiles, options: &LinkageOptions) -> Vec<Ignored> {
+    find_ignored_in(&RealFileSystem, dotfiles, options)
+}
+
+/// Same as `find_ignored`, but looks at the given filesystem
+pub(crate) fn find_ignored_in(fs: &dyn FileSystem, dotfiles: &Dotfiles, options: &LinkageOptions) -> Vec<Ignored> {
+    let linkfile = &dotfiles.linkfile;
+    let tags = options.tags.or_default(&linkfile.meta);
+
+    linkfile
+        .links
+        .iter()
+        .filter(|link| is_link_enabled(link, tags) && ignore::is_glob(&link.source))
+        .filter_map(|link| {
+            ignore::expand(fs, &dotfiles.root, &link.source, &link.exclude, &linkfile.meta.ignore).ok()
+        })
+        .flat_map(|expansion| expansion.ignored)
+        .collect()
+}
 
-    Ok(match mode {
-        Mode::Dry => dry_link_tasks(link_tasks),
-        Mode::Strict => link_strictly(link_tasks),
-        Mode::Lazy => link_lazy(link_tasks),
-        Mode::Force => link_forcefully(link_tasks),
+/// Decides which actions bring every examined target in line with the mode
+///
+/// The dry mode plans only the creation of absent targets to show which
+/// directories it requires, the lazy mode leaves out links that need no work.
+pub fn plan(dotfiles: &Dotfiles, options: &LinkageOptions) -> Result<Plan, Error> {
+    plan_in(&RealFileSystem, dotfiles, options)
+}
+
+/// Same as `plan`, but looks at the given filesystem
+pub(crate) fn plan_in(
+    fs: &dyn FileSystem,
+    dotfiles: &Dotfiles,
+    options: &LinkageOptions,
+) -> Result<Plan, Error> {
+    let normalize_links = options.normalize_links;
+    let on_conflict = |link_task: &LinkTask| {
+        let mode = options.strictness.mode_of(link_task, options.mode);
+        link_task.conflict_policy(mode, options.explicit_mode).map(|policy| policy.on_conflict)
+    };
+
+    let links = examine_in(fs, dotfiles, options)?
+        .into_iter()
+        .filter(|link_task| match options.mode {
+            Mode::Lazy => {
+                is_link_required(link_task, on_conflict(link_task), normalize_links)
+                    || is_link_task_source_missing(link_task)
+            }
+            _ => true,
+        })
+        .map(|link_task| {
+            let actions = match options.mode {
+                Mode::Dry => plan_actions(fs, &link_task, None, false),
+                _ => plan_actions(fs, &link_task, on_conflict(&link_task), normalize_links),
+            };
+            PlannedLink { task: link_task, actions }
+        })
+        .collect::<Vec<_>>();
+
+    if options.mode != Mode::Dry && users::is_root() && !options.allow_unsafe_sources {
+        let unsafe_sources = find_unsafe_sources(fs, &links);
+        if !unsafe_sources.is_empty() {
+            return Err(Error::UnsafeSources(unsafe_sources));
+        }
+    }
+
+    Ok(Plan {
+        linkfile: dotfiles.linkfile_path.clone(),
+        mode: options.mode,
+        explicit_mode: options.explicit_mode,
+        target_root: options.target_root.clone(),
+        strictness: options.strictness.clone(),
+        links,
     })
 }
 
-fn compare_link_tasks(l: &LinkTask, r: &LinkTask) -> Ordering {
-    // TODO: Figure out correct Discriminant with Ord trait implementation
+/// Executes the plan
+///
+/// State of every target is examined again right before acting on it,
+/// targets that changed since planning are left untouched.
+pub fn apply(dotfiles: &Dotfiles, plan: Plan) -> LinkageResult {
+    let decryptor = CommandDecryptor::new(&dotfiles.root, &dotfiles.linkfile.meta);
+    apply_in(&RealFileSystem, &decryptor, dotfiles, plan)
+}
 
-    let l_target_state = &l.target_state;
-    let r_target_state = &r.target_state;
+/// Same as `apply`, but changes the given filesystem and decrypts the
+/// encrypted sources with the given decryptor
+pub(crate) fn apply_in(
+    fs: &dyn FileSystem,
+    decryptor: &dyn Decryptor,
+    dotfiles: &Dotfiles,
+    plan: Plan,
+) -> LinkageResult {
+    let meta = &dotfiles.linkfile.meta;
+
+    let context = LinkageContext {
+        fs,
+        hook_runner: HookRunner::new(&dotfiles.root, plan.mode, &meta.hooks),
+        backup_policy: BackupPolicy::new(meta, &plan.target_root),
+        decryptor,
+    };
 
-    let l_discriminant = std::mem::discriminant(l_target_state);
-    let r_discriminant = std::mem::discriminant(r_target_state);
+    if plan.mode == Mode::Dry {
+        return dry_link_tasks(plan.links, &meta.hooks);
+    }
 
-    if l_discriminant == r_discriminant {
-        Ordering::Equal
-    } else {
-        match l_target_state {
-            TargetState::Absent => Ordering::Greater,
-            TargetState::AlienNode(_) => match r_target_state {
-                TargetState::Absent => Ordering::Less,
-                _ => Ordering::Greater,
-            },
-            TargetState::AlienLink(_) => match r_target_state {
-                TargetState::Linked(_) => Ordering::Greater,
-                _ => Ordering::Less,
-            },
-            TargetState::Linked(_) => Ordering::Less,
+    match find_failed_precondition(&plan.links, plan.mode, plan.explicit_mode, &plan.strictness) {
+        Some(precondition) => LinkageResult::PreconditionFailed(precondition),
+        None => execute_planned_links(plan.links, &context),
+    }
+}
+
+/// Finds paths users other than root and the link owner may change to
+/// redirect links created by root, both sources and their parents count
+fn find_unsafe_sources(fs: &dyn FileSystem, planned_links: &[PlannedLink]) -> Vec<PathBuf> {
+    let mut unsafe_paths: Vec<PathBuf> = Vec::new();
+
+    let linked_tasks = planned_links
+        .iter()
+        .filter(|planned_link| {
+            let is_linked = |action: &Action| {
+                matches!(action, Action::CreateSymlink { .. } | Action::Decrypt { .. })
+            };
+            planned_link.actions.iter().any(is_linked)
+        })
+        .map(|planned_link| &planned_link.task);
+
+    for link_task in linked_tasks {
+        let attributes = &link_task.attributes;
+        let is_trusted_user = |uid| uid == 0 || Some(uid) == attributes.uid;
+        let is_trusted_group = |gid| gid == 0 || Some(gid) == attributes.gid;
+        let is_unsafe = |path: &Path| match fs.stat(path) {
+            // Entries of sticky directories like `/tmp` may be renamed only by their owners
+            Ok(stat) if stat.mode & 0o1000 != 0 => !is_trusted_user(stat.uid),
+            Ok(stat) => {
+                !is_trusted_user(stat.uid)
+                    || (stat.mode & 0o020 != 0 && !is_trusted_group(stat.gid))
+                    || stat.mode & 0o002 != 0
+            }
+            Err(_) => true,
+        };
+
+        let unsafe_path = match fs.canonicalize(&link_task.source) {
+            Ok(source) => source.ancestors().find(|path| is_unsafe(path)).map(Path::to_path_buf),
+            Err(_) => Some(link_task.source.clone()),
+        };
+
+        if let Some(path) = unsafe_path {
+            if !unsafe_paths.contains(&path) {
+                unsafe_paths.push(path);
+            }
         }
     }
+
+    unsafe_paths
 }
 
-fn is_link_enabled(link: &Link, tags: &Vec<String>) -> bool {
+fn compare_link_tasks(l: &LinkTask, r: &LinkTask) -> Ordering {
+    // Satisfied links go first, then problems, then the work to do
+    let rank = |link_task: &LinkTask| match link_task.target_state {
+        TargetState::Linked => 0,
+        TargetState::LinkedIndirect => 1,
+        TargetState::SourceMissing => 2,
+        TargetState::AlienLink => 3,
+        TargetState::DanglingLink => 4,
+        TargetState::AlienNode => 5,
+        TargetState::Modified => 6,
+        TargetState::Outdated => 7,
+        TargetState::Absent => 8,
+    };
+
+    rank(l).cmp(&rank(r)).then_with(|| l.target.cmp(&r.target))
+}
+
+fn is_link_enabled(link: &Link, tags: &[String]) -> bool {
     match &link.tag {
         None => true,
-        Some(tag) => tags.contains(&tag),
+        Some(tag) => tags.contains(tag),
     }
 }
 
@@ -129,24 +644,30 @@ where
 }
 
 fn aggregate_link_tasks(
+    fs: &dyn FileSystem,
     environment: Environment,
     root: &path::Path,
-    links: &Vec<Link>,
-    tags: &Vec<String>,
+    linkfile: &Linkfile,
+    tags: &[String],
+    options: &LinkageOptions,
 ) -> Result<Vec<LinkTask>, Error> {
     let result: Vec<Vec<LinkTask>> = collect_all_results(
-        links
+        linkfile
+            .links
             .iter()
-            .filter(|link| is_link_enabled(link, &tags))
-            .map(|link| create_link_tasks(environment, root, link)),
-    ).map_err(|e| Error::LinkfileContentError(e))?;
+            .filter(|link| is_link_enabled(link, tags))
+            .map(|link| {
+                let meta = &linkfile.meta;
+                create_link_tasks(fs, environment, root, link, meta, options)
+            }),
+    ).map_err(Error::LinkfileContent)?;
 
     let result: Vec<LinkTask> = result.into_iter().flatten().collect();
 
     let mut dest_sets: HashMap<path::PathBuf, Vec<LinkTask>> = HashMap::new();
     for linktask in result.into_iter() {
         dest_sets.entry(linktask.target.clone())
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(linktask);
     }
 
@@ -169,14 +690,45 @@ fn aggregate_link_tasks(
 }
 
 fn create_link_tasks(
+    fs: &dyn FileSystem,
     environment: Environment,
     root: &path::Path,
     link: &Link,
+    meta: &Meta,
+    options: &LinkageOptions,
 ) -> Result<Vec<LinkTask>, (path::PathBuf, io::Error)> {
     let source = root.join(path::Path::new(&link.source));
+    let (attributes, parents) = link_attributes(link).map_err(|err| (source.clone(), err))?;
+    let on_conflict = match (link.on_conflict, meta.on_conflict) {
+        (Some(on_conflict), _) => Some(ConflictPolicy { on_conflict, source: PolicySource::Link }),
+        (None, Some(on_conflict)) => Some(ConflictPolicy { on_conflict, source: PolicySource::Meta }),
+        (None, None) => None,
+    };
 
-    let source_metadata = fs::metadata(&source)
-        .map_err(|e| (source.clone(), e))?;
+    let examined = if ignore::is_glob(&link.source) {
+        ignore::expand(fs, root, &link.source, &link.exclude, &meta.ignore).map(|expansion| {
+            let base = expansion.base;
+            expansion
+                .matches
+                .into_iter()
+                .map(|source| {
+                    let suffix = source.strip_prefix(&base).expect("Match is below the base").to_path_buf();
+                    (source, Some(suffix))
+                })
+                .collect()
+        })
+    } else {
+        fs.node_type(&source).map(|_| vec![(source.clone(), None)])
+    };
+
+    // Sources with the path they add to the target, one per wildcard match
+    let (sources, is_source_missing): (Vec<(PathBuf, Option<PathBuf>)>, bool) = match examined {
+        Ok(sources) => (sources, false),
+        Err(err) if err.kind() == io::ErrorKind::NotFound && options.allow_missing_sources => {
+            (vec![(source, None)], true)
+        }
+        Err(err) => return Err((source, err)),
+    };
 
     let destination = match &link.target {
         Target::Unified(destination) => destination,
@@ -198,39 +750,97 @@ fn create_link_tasks(
             .collect(),
     };
 
-    targets
-        .into_iter()
-        .map(|target| {
-            let target = expand_dest(&target);
-            let target_state = examine_target_state(&target, &source)
-                .map_err(|e| (target.clone(), e))?;
-            Ok(LinkTask {
+    let mut link_tasks = Vec::new();
+    for target in targets {
+        let target = options.target_root.resolve(&target);
+        if !target.is_absolute() {
+            let err = io::Error::new(io::ErrorKind::InvalidInput, "target is not absolute");
+            return Err((target, err));
+        }
+
+        for (source, suffix) in &sources {
+            let target = match suffix {
+                Some(suffix) => target.join(suffix),
+                None => target.clone(),
+            };
+            let deployed = link.encryption.and_then(|_| options.deployed.get(&target).cloned());
+            let target_state = if is_source_missing {
+                TargetState::SourceMissing
+            } else {
+                examine_target_state(fs, &target, source, link.encryption, deployed.as_deref())
+                    .map_err(|e| (target.clone(), e))?
+            };
+            link_tasks.push(LinkTask {
                 source: source.clone(),
-                source_metadata: source_metadata.clone(),
-                target: target,
-                target_state: target_state,
-            })
-        })
-        .collect()
+                target,
+                target_state,
+                tag: link.tag.clone(),
+                hooks: link.hooks.clone(),
+                attributes,
+                parents,
+                encryption: link.encryption,
+                on_conflict,
+                deployed,
+            });
+        }
+    }
+
+    Ok(link_tasks)
+}
+
+fn link_attributes(link: &Link) -> io::Result<(Attributes, ParentPolicy)> {
+    let ownership = &link.ownership;
+    let attributes = Attributes {
+        uid: ownership.owner.as_deref().map(users::user_id).transpose()?,
+        gid: ownership.group.as_deref().map(users::group_id).transpose()?,
+        mode: ownership.mode.as_deref().map(parse_mode).transpose()?,
+    };
+
+    // Mode of the link is meant for files, directories keep the default one
+    let parents = ParentPolicy {
+        create: link.create_parents.unwrap_or(true),
+        attributes: Attributes {
+            mode: ownership.parent_mode.as_deref().map(parse_mode).transpose()?,
+            ..attributes
+        },
+    };
+
+    Ok((attributes, parents))
+}
+
+fn parse_mode(mode: &str) -> io::Result<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("invalid mode: {}", mode))
+    })
 }
 
-fn examine_target_state(target: &path::Path, source: &path::Path) -> io::Result<TargetState> {
+fn examine_target_state(
+    fs: &dyn FileSystem,
+    target: &path::Path,
+    source: &path::Path,
+    encryption: Option<Encryption>,
+    deployed: Option<&str>,
+) -> io::Result<TargetState> {
     assert!(target.is_absolute());
     assert!(source.is_absolute());
 
-    let target_metadata = match fs::symlink_metadata(target) {
-        Ok(metadata) => metadata,
+    if encryption.is_some() {
+        return examine_decrypted_state(fs, target, source, deployed);
+    }
+
+    let target_type = match fs.symlink_node_type(target) {
+        Ok(node_type) => node_type,
         Err(err) if err.kind() == io::ErrorKind::NotFound => {
             return Ok(TargetState::Absent);
         }
         Err(err) => return Err(err),
     };
 
-    if !target_metadata.file_type().is_symlink() {
-        return Ok(TargetState::AlienNode(target_metadata));
+    if target_type != NodeType::Symlink {
+        return Ok(TargetState::AlienNode);
     }
 
-    let target_destination = fs::read_link(target)?;
+    let target_destination = fs.read_link(target)?;
 
     let resolved_target_destination = if target_destination.is_absolute() {
         target_destination
@@ -244,198 +854,1311 @@ fn examine_target_state(target: &path::Path, source: &path::Path) -> io::Result<
         parent.join(target_destination)
     };
 
-    if resolved_target_destination != source {
-        return Ok(TargetState::AlienLink(target_metadata));
+    // Relative destinations reach the source through `..`, it counts as
+    // direct unless it steps out of a symlinked directory
+    let is_direct = resolved_target_destination == source
+        || (normalize(&resolved_target_destination) == normalize(source)
+            && fs.canonicalize(&resolved_target_destination).ok() == fs.canonicalize(source).ok());
+
+    if !is_direct {
+        let canonical_target_destination = match fs.canonicalize(&resolved_target_destination) {
+            Ok(destination) => destination,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(TargetState::DanglingLink);
+            }
+            Err(err) => return Err(err),
+        };
+
+        if canonical_target_destination != fs.canonicalize(source)? {
+            return Ok(TargetState::AlienLink);
+        }
+
+        return Ok(TargetState::LinkedIndirect);
     }
 
-    Ok(TargetState::Linked(target_metadata))
+    Ok(TargetState::Linked)
 }
 
-fn dry_link_tasks(linktasks: Vec<LinkTask>) -> LinkageResult {
-    LinkageResult::DryResult(linktasks)
-}
+/// Decrypted copies are regular files, they are up to date as long as
+/// they are not older than the source
+///
+/// Copies whose content differs from the deployed one were edited in place,
+/// the modification time alone does not tell it.
+fn examine_decrypted_state(
+    fs: &dyn FileSystem,
+    target: &path::Path,
+    source: &path::Path,
+    deployed: Option<&str>,
+) -> io::Result<TargetState> {
+    match fs.symlink_node_type(target) {
+        Ok(NodeType::File) => {}
+        Ok(NodeType::Dir) => return Ok(TargetState::AlienNode),
+        Ok(NodeType::Symlink) => {
+            return match fs.node_type(target) {
+                Ok(_) => Ok(TargetState::AlienLink),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(TargetState::DanglingLink),
+                Err(err) => Err(err),
+            };
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(TargetState::Absent),
+        Err(err) => return Err(err),
+    }
 
-fn is_link_task_target_absent(link_task: &LinkTask) -> bool {
-    if let TargetState::Absent = link_task.target_state {
-        true
+    if let Some(deployed) = deployed {
+        if drift::hash(&fs.read(target)?) != deployed {
+            return Ok(TargetState::Modified);
+        }
+    }
+
+    if fs.modified(target)? >= fs.modified(source)? {
+        Ok(TargetState::Linked)
     } else {
-        false
+        Ok(TargetState::Outdated)
     }
 }
 
-fn link_strictly(link_tasks: Vec<LinkTask>) -> LinkageResult {
-    if !link_tasks.iter().all(is_link_task_target_absent) {
-        return LinkageResult::PreconditionFailed("Some of the targets exists");
-    }
+fn dry_link_tasks(planned_links: Vec<PlannedLink>, hooks: &Hooks) -> LinkageResult {
+    LinkageResult::DryResult(planned_links, hooks.clone())
+}
 
-    let result = link_tasks
-        .into_iter()
-        .map(|link_task| LinkState {
-            result: execute_linktask(&link_task, false),
-            task: link_task,
-        })
-        .collect();
+fn is_link_task_target_absent(link_task: &LinkTask) -> bool {
+    matches!(link_task.target_state, TargetState::Absent)
+}
 
-    LinkageResult::Completed(result)
+fn is_link_task_source_missing(link_task: &LinkTask) -> bool {
+    matches!(link_task.target_state, TargetState::SourceMissing)
 }
 
-fn link_lazy(link_tasks: Vec<LinkTask>) -> LinkageResult {
-    let result = link_tasks
-        .into_iter()
-        .filter(is_link_task_target_absent)
-        .map(|link_task| LinkState {
-            result: execute_linktask(&link_task, false),
-            task: link_task,
+/// Refuses the linkage when targets that may not be touched are occupied
+///
+/// The strict mode requires every target of the links in its scope without
+/// a policy of their own to be absent, the linked ones too unless allowed.
+fn find_failed_precondition(
+    planned_links: &[PlannedLink],
+    mode: Mode,
+    explicit_mode: bool,
+    strictness: &Strictness,
+) -> Option<Precondition> {
+    let is_target_free = |link_task: &LinkTask| {
+        let is_linked = matches!(
+            link_task.target_state,
+            TargetState::Linked | TargetState::LinkedIndirect
+        );
+        is_link_task_target_absent(link_task)
+            || is_link_task_source_missing(link_task)
+            || (is_linked && strictness.allow_linked)
+    };
+
+    // Blocking tasks keep only the policy of the linkfile that refused them
+    let blocking: Vec<LinkTask> = planned_links
+        .iter()
+        .map(|planned_link| &planned_link.task)
+        .filter_map(|link_task| {
+            let policy = link_task.conflict_policy(strictness.mode_of(link_task, mode), explicit_mode)?;
+            let on_conflict = match policy.on_conflict {
+                OnConflict::Fail if policy.source.is_mode() && !is_target_free(link_task) => None,
+                OnConflict::Fail if !policy.source.is_mode() && link_task.is_conflict() => Some(policy),
+                _ => return None,
+            };
+            Some(LinkTask { on_conflict, ..link_task.clone() })
         })
         .collect();
 
-    LinkageResult::Completed(result)
+    (!blocking.is_empty()).then_some(Precondition::TargetsExist(blocking))
 }
 
-fn link_forcefully(link_tasks: Vec<LinkTask>) -> LinkageResult {
-    let result = link_tasks
+fn execute_planned_links(
+    planned_links: Vec<PlannedLink>,
+    context: &LinkageContext,
+) -> LinkageResult {
+    let hook_runner = &context.hook_runner;
+    let mut hook_runs = Vec::new();
+
+    if planned_links.iter().any(|planned_link| !planned_link.actions.is_empty()) {
+        if let Some(hook_run) = hook_runner.run_for_linkage(HookKind::PreLink) {
+            if hook_run.is_failure() {
+                return LinkageResult::HookFailed(hook_run);
+            }
+            hook_runs.push(hook_run);
+        }
+    }
+
+    let link_states: Vec<LinkState> = planned_links
         .into_iter()
-        .map(|link_task| LinkState {
-            result: execute_linktask(&link_task, true),
-            task: link_task,
-        })
+        .map(|planned_link| execute_planned_link(planned_link, context))
         .collect();
 
-    LinkageResult::Completed(result)
-}
+    if link_states.iter().any(|link_state| matches!(link_state.result, LinkResult::Success)) {
+        hook_runs.extend(hook_runner.run_for_linkage(HookKind::PostLink));
+    }
 
-fn link_without_overriding(source: &Path, target: &Path) -> LinkResult {
-    assert!(source.exists());
-    assert!(!target.exists());
+    LinkageResult::Completed(link_states, hook_runs)
+}
 
-    if let Err(err) = platform_link(&source, &target) {
-        LinkResult::IoError(err)
-    } else {
-        LinkResult::Success
+fn is_link_required(link_task: &LinkTask, on_conflict: Option<OnConflict>, normalize_links: bool) -> bool {
+    match link_task.target_state {
+        TargetState::Absent => true,
+        TargetState::AlienNode
+        | TargetState::AlienLink
+        | TargetState::DanglingLink
+        | TargetState::Outdated
+        | TargetState::Modified => {
+            matches!(on_conflict, Some(OnConflict::Backup | OnConflict::Replace | OnConflict::Adopt))
+        }
+        TargetState::LinkedIndirect => normalize_links,
+        TargetState::Linked | TargetState::SourceMissing => false,
     }
 }
 
-fn link_with_overriding(source: &Path, target: &Path) -> LinkResult {
-    assert!(source.exists());
-    assert!(target.exists());
+fn plan_actions(
+    fs: &dyn FileSystem,
+    link_task: &LinkTask,
+    on_conflict: Option<OnConflict>,
+    normalize_links: bool,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
 
-    if let Err(err) = backup_target(&target) {
-        LinkResult::IoError(err)
-    } else {
-        link_without_overriding(&source, &target)
+    if !is_link_required(link_task, on_conflict, normalize_links) {
+        return actions;
     }
-}
 
-fn backup_target(target: &Path) -> io::Result<()> {
-    assert!(target.exists());
+    let target = &link_task.target;
+    let attributes = link_task.attributes;
+    let parents = link_task.parents;
 
-    let new_target_name = find_free_backup_target_name(target).ok_or(io::Error::new(
-        io::ErrorKind::Other,
-        "Cannot find suitable backup name",
-    ))?;
+    match link_task.target_state {
+        TargetState::Absent => {
+            let missing_parents: Vec<&Path> = target
+                .ancestors()
+                .skip(1)
+                .take_while(|parent| fs.node_type(parent).is_err())
+                .collect();
+            if !missing_parents.is_empty() && !parents.create {
+                return actions;
+            }
+            for parent in missing_parents.into_iter().rev() {
+                let path = parent.to_path_buf();
+                actions.push(Action::CreateParent { path: path.clone() });
+                if !parents.attributes.is_empty() {
+                    actions.push(Action::SetAttributes { path, attributes: parents.attributes });
+                }
+            }
+        }
+        TargetState::LinkedIndirect => actions.push(Action::Remove { path: target.clone() }),
+        _ => match on_conflict {
+            Some(OnConflict::Replace) => actions.push(Action::Delete { path: target.clone() }),
+            // Only files and directories are worth keeping, plaintext of the
+            // encrypted sources never goes to the linkfile directory
+            Some(OnConflict::Adopt)
+                if link_task.target_state == TargetState::AlienNode && link_task.encryption.is_none() =>
+            {
+                let source = link_task.source.clone();
+                actions.push(Action::BackupSource { path: source.clone() });
+                actions.push(Action::Adopt { source, target: target.clone() });
+            }
+            _ => actions.push(Action::Backup { path: target.clone() }),
+        },
+    }
 
-    fs::rename(target, new_target_name)
+    if let Some(encryption) = link_task.encryption {
+        actions.push(Action::Decrypt {
+            source: link_task.source.clone(),
+            target: target.clone(),
+            encryption,
+        });
+        if !attributes.is_empty() {
+            actions.push(Action::SetAttributes { path: target.clone(), attributes });
+        }
+        return actions;
+    }
+
+    actions.push(Action::CreateSymlink {
+        source: link_task.source.clone(),
+        target: target.clone(),
+    });
+
+    let attributes = Attributes { mode: None, ..attributes };
+    if !attributes.is_empty() {
+        actions.push(Action::SetAttributes { path: target.clone(), attributes });
+    }
+
+    actions
 }
 
-fn find_free_backup_target_name(target: &Path) -> Option<PathBuf> {
-    let target_components: Vec<_> = target.components().collect();
-    let (last, elements) = target_components.split_last()?;
+fn execute_planned_link(planned_link: PlannedLink, context: &LinkageContext) -> LinkState {
+    let hook_runner = &context.hook_runner;
+    let PlannedLink { task: link_task, actions } = planned_link;
+    let mut hooks = Vec::new();
+    let mut executed = Vec::new();
+
+    if actions.is_empty() {
+        let result = match link_task.target_state {
+            TargetState::Linked | TargetState::LinkedIndirect => LinkResult::Existed,
+            TargetState::SourceMissing => LinkResult::SourceMissing,
+            // Absent targets are always linked unless parents may not be created
+            TargetState::Absent => LinkResult::ParentMissing,
+            _ => LinkResult::Skipped,
+        };
+        return LinkState { task: link_task, result, actions: executed, hooks };
+    }
 
-    let name_constructor = |i| {
-        let mut new_end = std::ffi::OsString::new();
-        new_end.push(last);
-        new_end.push(format!(".bak.{}", i));
+    let target_state = examine_target_state(
+        context.fs,
+        &link_task.target,
+        &link_task.source,
+        link_task.encryption,
+        link_task.deployed.as_deref(),
+    );
+    match target_state {
+        Ok(target_state) if target_state == link_task.target_state => {}
+        Ok(target_state) => {
+            let result = LinkResult::StateChanged(target_state);
+            return LinkState { task: link_task, result, actions: executed, hooks };
+        }
+        Err(err) => {
+            let result = LinkResult::IoError(err);
+            return LinkState { task: link_task, result, actions: executed, hooks };
+        }
+    }
 
-        let new_component = path::Component::Normal(&new_end);
+    let run_hook = |kind| {
+        hook_runner.run_for_link(&link_task.hooks, kind, &link_task.source, &link_task.target)
+    };
 
-        let new_target: PathBuf = [elements, std::slice::from_ref(&new_component)]
-            .concat()
-            .iter()
-            .collect();
+    if let Some(hook_run) = run_hook(HookKind::PreLink) {
+        let failed = hook_run.is_failure();
+        hooks.push(hook_run);
+        if failed {
+            let result = LinkResult::Skipped;
+            return LinkState { task: link_task, result, actions: executed, hooks };
+        }
+    }
 
-        new_target
+    let execution = actions.into_iter().try_for_each(|action| {
+        execute_action(&action, context)?;
+        executed.push(action);
+        Ok(())
+    });
+
+    let result = match execution {
+        Err(err) => LinkResult::IoError(err),
+        Ok(()) if link_task.target_state == TargetState::LinkedIndirect => LinkResult::Normalized,
+        Ok(()) => LinkResult::Success,
     };
 
-    for i in 1..100 {
-        let new_target_name = name_constructor(i);
+    if let LinkResult::Success = result {
+        hooks.extend(run_hook(HookKind::PostLink));
+    }
 
-        if !new_target_name.exists() {
-            return Some(new_target_name);
+    LinkState { task: link_task, result, actions: executed, hooks }
+}
+
+fn execute_action(action: &Action, context: &LinkageContext) -> io::Result<()> {
+    match action {
+        Action::CreateParent { path } => create_parent(context.fs, path),
+        Action::Backup { path } => context.backup_policy.backup(context.fs, path).map(|_| ()),
+        Action::BackupSource { path } => context.backup_policy.backup_source(context.fs, path).map(|_| ()),
+        Action::Remove { path } => context.fs.remove_link(path),
+        Action::Delete { path } => context.fs.remove_all(path),
+        Action::Adopt { source, target } => context.fs.rename(target, source),
+        Action::CreateSymlink { source, target } => context.fs.symlink(source, target),
+        Action::Decrypt { source, target, encryption } => {
+            decrypt(context.fs, context.decryptor, source, target, *encryption)
+        }
+        Action::SetAttributes { path, attributes } => {
+            set_attributes(context.fs, path, attributes)
         }
     }
+}
 
-    None
+/// Writes the plaintext of the source to the new target readable only by
+/// the owner, plaintext never ends up anywhere else unless `[meta]
+/// merge_base` asks to keep it for `drift --merge`
+fn decrypt(
+    fs: &dyn FileSystem,
+    decryptor: &dyn Decryptor,
+    source: &Path,
+    target: &Path,
+    encryption: Encryption,
+) -> io::Result<()> {
+    let ciphertext = fs.read(source)?;
+    let plaintext = decryptor.decrypt(encryption, &ciphertext)?;
+    fs.create_file(target, &plaintext, 0o600)
 }
 
-fn execute_linktask(link_task: &LinkTask, overwrite: bool) -> LinkResult {
-    match link_task.target_state {
-        TargetState::Absent => link_without_overriding(&link_task.source, &link_task.target),
-        TargetState::AlienNode(_) => {
-            if overwrite {
-                link_with_overriding(&link_task.source, &link_task.target)
-            } else {
-                LinkResult::Skipped
+fn set_attributes(fs: &dyn FileSystem, path: &Path, attributes: &Attributes) -> io::Result<()> {
+    if attributes.uid.is_some() || attributes.gid.is_some() {
+        fs.set_owner(path, attributes.uid, attributes.gid)?;
+    }
+    if let Some(mode) = attributes.mode {
+        fs.set_mode(path, mode)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn expand_dest(dest: &Path) -> PathBuf {
+    let home_dir = match home_user(dest) {
+        Some("") => dirs::home_dir(),
+        Some(user) => users::home_dir(user),
+        None => None,
+    };
+
+    match home_dir {
+        Some(home_dir) => replace_home(dest, &home_dir),
+        None => dest.to_path_buf(),
+    }
+}
+
+/// User of the leading `~user` component, empty for the plain `~`
+fn home_user(dest: &Path) -> Option<&str> {
+    match dest.components().next()? {
+        Component::Normal(first) => first.to_str()?.strip_prefix('~'),
+        _ => None,
+    }
+}
+
+/// Replaces the leading `~` component of the path with the home directory
+fn replace_home(dest: &Path, home_dir: &Path) -> PathBuf {
+    let home_dir_components: Vec<_> = home_dir.components().collect();
+    let start_components = &home_dir_components[..];
+
+    let dest_components: Vec<_> = dest.components().collect();
+    let end_components = &dest_components[1..];
+
+    [start_components, end_components].concat().iter().collect()
+}
+
+fn create_parent(fs: &dyn FileSystem, target_dir: &Path) -> io::Result<()> {
+    match fs.node_type(target_dir) {
+        Ok(NodeType::Dir) => Ok(()),
+        // TODO: Replace error type
+        Ok(_) => Err(io::Error::other("Parent path for the target is not directory!")),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => fs.create_dir_all(target_dir),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::filesystem::{MemoryFileSystem, MemoryNode, Stat};
+    use crate::secrets::FakeDecryptor;
+
+    const LINKFILE: &str = r#"
+        [[link]]
+        source = "vimrc"
+        target = "/home/link/.vimrc"
+
+        [[link]]
+        source = "nvim"
+        target = "/home/link/.config/nvim"
+    "#;
+
+    fn dotfiles(content: &str) -> Dotfiles {
+        Dotfiles {
+            linkfile_path: PathBuf::from("/dotfiles/dotfiles.toml"),
+            root: PathBuf::from("/dotfiles"),
+            linkfile: toml::from_str(content).unwrap(),
+            format: Format::Toml,
+        }
+    }
+
+    fn filesystem() -> MemoryFileSystem {
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/dotfiles.toml");
+        fs.add_file("/dotfiles/vimrc");
+        fs.add_dir("/dotfiles/nvim");
+        fs.add_dir("/home/link");
+        fs
+    }
+
+    fn options(mode: Mode) -> LinkageOptions {
+        LinkageOptions { mode, ..LinkageOptions::default() }
+    }
+
+    fn decryptor() -> FakeDecryptor {
+        FakeDecryptor::new(b"key")
+    }
+
+    fn link(fs: &MemoryFileSystem, dotfiles: &Dotfiles, mode: Mode) -> LinkageResult {
+        let plan = plan_in(fs, dotfiles, &options(mode)).unwrap();
+        apply_in(fs, &decryptor(), dotfiles, plan)
+    }
+
+    fn results(result: &LinkageResult) -> Vec<(String, String)> {
+        match result {
+            LinkageResult::Completed(link_states, _) => link_states
+                .iter()
+                .map(|state| (state.task.target.display().to_string(), format!("{:?}", state.result)))
+                .collect(),
+            result => panic!("Linkage was not completed: {:?}", result),
+        }
+    }
+
+    fn symlink(destination: &str) -> Option<MemoryNode> {
+        Some(MemoryNode::Symlink(PathBuf::from(destination)))
+    }
+
+    /// The only timestamped directory of the backups
+    fn backup_run(fs: &MemoryFileSystem, backup_dir: &str) -> PathBuf {
+        let runs = fs.read_dir(Path::new(backup_dir)).unwrap();
+        assert_eq!(runs.len(), 1, "{:?}", runs);
+        runs[0].clone()
+    }
+
+    #[test]
+    fn examine_target_states() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vimrc"
+            target = [
+                "/t/absent", "/t/node", "/t/alien", "/t/escaped", "/t/dangling", "/t/linked", "/t/relative",
+                "/t/indirect",
+            ]
+
+            [[link]]
+            source = "missing"
+            target = "/t/missing"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file("/t/node");
+        fs.add_symlink("/t/alien", "/dotfiles/dotfiles.toml");
+        fs.add_symlink("/t/dangling", "/dotfiles/gone");
+        fs.add_symlink("/t/linked", "/dotfiles/vimrc");
+        fs.add_symlink("/t/relative", "../dotfiles/vimrc");
+        // `..` steps out of the symlinked directory, not back to `/t`
+        fs.add_symlink("/t/deep", "/a/b/c");
+        fs.add_dir("/a/b/c");
+        fs.add_file("/a/dotfiles/vimrc");
+        fs.add_symlink("/t/escaped", "deep/../../dotfiles/vimrc");
+        fs.add_symlink("/t/dotfiles", "/dotfiles");
+        fs.add_symlink("/t/indirect", "dotfiles/vimrc");
+
+        let options = LinkageOptions { allow_missing_sources: true, ..options(Mode::Dry) };
+        let states: Vec<_> = examine_in(&fs, &dotfiles, &options)
+            .unwrap()
+            .into_iter()
+            .map(|task| (task.target.display().to_string(), task.target_state))
+            .collect();
+
+        assert_eq!(
+            states,
+            vec![
+                ("/t/linked".to_string(), TargetState::Linked),
+                ("/t/relative".to_string(), TargetState::Linked),
+                ("/t/indirect".to_string(), TargetState::LinkedIndirect),
+                ("/t/missing".to_string(), TargetState::SourceMissing),
+                ("/t/alien".to_string(), TargetState::AlienLink),
+                ("/t/escaped".to_string(), TargetState::AlienLink),
+                ("/t/dangling".to_string(), TargetState::DanglingLink),
+                ("/t/node".to_string(), TargetState::AlienNode),
+                ("/t/absent".to_string(), TargetState::Absent),
+            ]
+        );
+    }
+
+    #[test]
+    fn examine_fails_on_missing_source() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = MemoryFileSystem::new();
+        fs.add_dir("/dotfiles/nvim");
+
+        match examine_in(&fs, &dotfiles, &options(Mode::Dry)) {
+            Err(Error::LinkfileContent(errors)) => {
+                assert_eq!(errors.len(), 1);
+                assert_eq!(errors[0].0, PathBuf::from("/dotfiles/vimrc"));
             }
+            result => panic!("Unexpected result: {:?}", result),
         }
-        TargetState::AlienLink(_) => {
-            if overwrite {
-                link_with_overriding(&link_task.source, &link_task.target)
-            } else {
-                LinkResult::Skipped
+    }
+
+    #[test]
+    fn examine_fails_on_conflicting_targets() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vimrc"
+            target = "/home/link/.vimrc"
+
+            [[link]]
+            source = "nvim"
+            target = "/home/link/.vimrc"
+            "#,
+        );
+
+        let result = examine_in(&filesystem(), &dotfiles, &options(Mode::Dry));
+        assert!(matches!(result, Err(Error::TargetConflict(_))));
+    }
+
+    #[test]
+    fn wildcard_sources_link_every_match() {
+        let dotfiles = dotfiles(
+            r#"
+            [meta]
+            ignore = [".DS_Store"]
+
+            [[link]]
+            source = "bin/*"
+            target = "/home/link/.local/bin"
+            exclude = ["README*"]
+
+            [[link]]
+            source = "nvim/**"
+            target = "/home/link/.config/nvim"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file("/dotfiles/bin/backup");
+        fs.add_file("/dotfiles/bin/README.md");
+        fs.add_file("/dotfiles/bin/.DS_Store");
+        fs.add_file("/dotfiles/nvim/init.lua");
+        fs.add_file("/dotfiles/nvim/lua/plugins.lua");
+        fs.add_file("/dotfiles/nvim/.init.lua.swp");
+        fs.add_file_content("/dotfiles/.rinkuignore", b"*.swp\n");
+
+        assert_eq!(
+            results(&link(&fs, &dotfiles, Mode::Lazy)),
+            vec![
+                ("/home/link/.config/nvim/init.lua".to_string(), "Success".to_string()),
+                ("/home/link/.config/nvim/lua/plugins.lua".to_string(), "Success".to_string()),
+                ("/home/link/.local/bin/backup".to_string(), "Success".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.config/nvim/lua/plugins.lua"), symlink("/dotfiles/nvim/lua/plugins.lua"));
+
+        let ignored: Vec<String> = find_ignored_in(&fs, &dotfiles, &options(Mode::Dry))
+            .into_iter()
+            .map(|ignored| format!("{} {}", ignored.source.display(), ignored.rule))
+            .collect();
+        assert_eq!(
+            ignored,
+            vec![
+                "/dotfiles/bin/.DS_Store meta ignore: .DS_Store",
+                "/dotfiles/bin/README.md exclude: README*",
+                "/dotfiles/nvim/.init.lua.swp .rinkuignore:1: *.swp",
+            ]
+        );
+    }
+
+    #[test]
+    fn conflict_policies_override_mode() {
+        let dotfiles = dotfiles(
+            r#"
+            [meta]
+            on_conflict = "skip"
+            backup_dir = "/backups"
+
+            [[link]]
+            source = "vimrc"
+            target = "/home/link/.vimrc"
+
+            [[link]]
+            source = "nvim"
+            target = "/home/link/.config/nvim"
+            on_conflict = "replace"
+
+            [[link]]
+            source = "gitconfig"
+            target = "/home/link/.gitconfig"
+            on_conflict = "adopt"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+        fs.add_file("/home/link/.config/nvim/init.lua");
+        fs.add_file_content("/dotfiles/gitconfig", b"[user]\n");
+        fs.add_file_content("/home/link/.gitconfig", b"[user]\nname = link\n");
+
+        assert_eq!(
+            results(&link(&fs, &dotfiles, Mode::Force)),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+                ("/home/link/.gitconfig".to_string(), "Success".to_string()),
+                ("/home/link/.vimrc".to_string(), "Skipped".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/home/link/.config/nvim"), symlink("/dotfiles/nvim"));
+        assert_eq!(fs.node("/home/link/.config/nvim/init.lua"), None);
+        assert_eq!(fs.node("/home/link/.gitconfig"), symlink("/dotfiles/gitconfig"));
+        assert_eq!(fs.content("/dotfiles/gitconfig"), Some(b"[user]\nname = link\n".to_vec()));
+        assert_eq!(fs.node("/dotfiles/gitconfig.bak.1"), None);
+        let backup = backup_run(&fs, "/backups").join("dotfiles/gitconfig.bak.1");
+        assert_eq!(fs.content(backup.to_str().unwrap()), Some(b"[user]\n".to_vec()));
+    }
+
+    #[test]
+    fn explicit_modes_override_meta_policy() {
+        let dotfiles = dotfiles(&format!("[meta]\non_conflict = \"replace\"\n{}", LINKFILE));
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+        let link_explicitly = |mode| {
+            let options = LinkageOptions { mode, explicit_mode: true, ..LinkageOptions::default() };
+            let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+            apply_in(&fs, &decryptor(), &dotfiles, plan)
+        };
+
+        assert_eq!(
+            results(&link_explicitly(Mode::Lazy)),
+            vec![("/home/link/.config/nvim".to_string(), "Success".to_string())]
+        );
+        assert!(matches!(
+            link_explicitly(Mode::Strict),
+            LinkageResult::PreconditionFailed(Precondition::TargetsExist(_))
+        ));
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+
+        // Default mode of the command, e.g. in `watch`, gives way to the policy
+        assert_eq!(
+            results(&link(&fs, &dotfiles, Mode::Lazy)),
+            vec![("/home/link/.vimrc".to_string(), "Success".to_string())]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
+
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+        let options = LinkageOptions { mode: Mode::Force, explicit_mode: true, ..LinkageOptions::default() };
+        let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+        apply_in(&fs, &decryptor(), &dotfiles, plan);
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.1"), Some(MemoryNode::File));
+    }
+
+    #[test]
+    fn policy_of_highest_precedence_is_resolved() {
+        let policy = |on_conflict, source| Some(ConflictPolicy { on_conflict, source });
+        let link = policy(OnConflict::Adopt, PolicySource::Link);
+        let meta = policy(OnConflict::Replace, PolicySource::Meta);
+
+        assert_eq!(ConflictPolicy::resolve(link, ConflictPolicy::of_mode(Mode::Force, true)), link);
+        assert_eq!(
+            ConflictPolicy::resolve(meta, ConflictPolicy::of_mode(Mode::Force, true)),
+            policy(OnConflict::Backup, PolicySource::ExplicitMode(Mode::Force))
+        );
+        assert_eq!(ConflictPolicy::resolve(meta, ConflictPolicy::of_mode(Mode::Lazy, false)), meta);
+        assert_eq!(ConflictPolicy::resolve(meta, ConflictPolicy::of_mode(Mode::Dry, true)), meta);
+        assert_eq!(ConflictPolicy::resolve(None, ConflictPolicy::of_mode(Mode::Dry, false)), None);
+    }
+
+    #[test]
+    fn failing_conflict_policy_refuses_linkage() {
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+
+        let failing = dotfiles(&format!("[meta]\non_conflict = \"fail\"\n{}", LINKFILE));
+        let result = link(&fs, &failing, Mode::Force);
+        assert!(matches!(result, LinkageResult::PreconditionFailed(Precondition::TargetsExist(_))));
+
+        let result = link(&fs, &dotfiles(LINKFILE), Mode::Strict);
+        assert!(matches!(result, LinkageResult::PreconditionFailed(Precondition::TargetsExist(_))));
+
+        let skipping = dotfiles(&format!("[meta]\non_conflict = \"skip\"\n{}", LINKFILE));
+        assert_eq!(
+            results(&link(&fs, &skipping, Mode::Strict)),
+            vec![
+                ("/home/link/.vimrc".to_string(), "Skipped".to_string()),
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn target_root_resolution() {
+        let target_root = TargetRoot {
+            home: Some(PathBuf::from("/home/guest")),
+            destdir: Some(PathBuf::from("/image")),
+        };
+
+        assert_eq!(
+            target_root.resolve(Path::new("~/.vimrc")),
+            PathBuf::from("/image/home/guest/.vimrc")
+        );
+        assert_eq!(
+            target_root.resolve(Path::new("/etc/motd")),
+            PathBuf::from("/image/etc/motd")
+        );
+    }
+
+    #[test]
+    fn destdir_mode_links_under_prefix() {
+        let dotfiles = dotfiles(
+            r#"
+            [meta]
+            backup_dir = "~/.backups"
+
+            [[link]]
+            source = "vimrc"
+            target = ["~/.vimrc", "/etc/vimrc"]
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file("/image/home/guest/.vimrc");
+
+        let options = LinkageOptions {
+            target_root: TargetRoot {
+                home: Some(PathBuf::from("/home/guest")),
+                destdir: Some(PathBuf::from("/image")),
+            },
+            ..options(Mode::Force)
+        };
+        let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+
+        assert_eq!(results(&result).len(), 2);
+        assert_eq!(fs.node("/image/home/guest/.vimrc"), symlink("/dotfiles/vimrc"));
+        assert_eq!(fs.node("/image/etc/vimrc"), symlink("/dotfiles/vimrc"));
+        let run = backup_run(&fs, "/image/home/guest/.backups");
+        assert_eq!(fs.node(run.join("home/guest/.vimrc.bak.1")), Some(MemoryNode::File));
+        assert_eq!(fs.node("/etc/vimrc"), None);
+    }
+
+    #[test]
+    fn expand_home_of_other_user() {
+        let root_home = users::home_dir("root").unwrap();
+        assert_eq!(expand_dest(Path::new("~root/.vimrc")), root_home.join(".vimrc"));
+
+        let unknown = Path::new("~nonexistent-user/.vimrc");
+        assert_eq!(expand_dest(unknown), unknown.to_path_buf());
+    }
+
+    #[test]
+    fn ownership_applied_to_link_and_parents() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "nvim"
+            target = "/home/guest/.config/nvim"
+            owner = "1000"
+            group = "100"
+            mode = "0600"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_dir("/home/guest");
+
+        let result = link(&fs, &dotfiles, Mode::Strict);
+
+        assert_eq!(results(&result).len(), 1);
+        // File mode would make the directory impossible to enter
+        assert_eq!(
+            fs.node_stat("/home/guest/.config"),
+            Some(Stat { uid: 1000, gid: 100, mode: 0o755 })
+        );
+        assert_eq!(
+            fs.node_stat("/home/guest/.config/nvim"),
+            Some(Stat { uid: 1000, gid: 100, mode: 0o777 })
+        );
+        assert_eq!(fs.node_stat("/home/guest"), Some(Stat { uid: 0, gid: 0, mode: 0o755 }));
+    }
+
+    #[test]
+    fn parent_policy_of_link() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vimrc"
+            target = "/home/link/.ssh/config"
+            mode = "0640"
+            parent_mode = "0700"
+
+            [[link]]
+            source = "nvim"
+            target = "/home/link/.confgi/nvim"
+            create_parents = false
+            "#,
+        );
+        let fs = filesystem();
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.confgi/nvim".to_string(), "ParentMissing".to_string()),
+                ("/home/link/.ssh/config".to_string(), "Success".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.confgi"), None);
+        assert_eq!(fs.node_stat("/home/link/.ssh"), Some(Stat { uid: 0, gid: 0, mode: 0o700 }));
+    }
+
+    #[test]
+    fn unsafe_sources_are_found() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vimrc"
+            target = "/etc/vimrc"
+
+            [[link]]
+            source = "guest/bashrc"
+            target = "/home/guest/.bashrc"
+            owner = "1000"
+
+            [[link]]
+            source = "shared/motd"
+            target = "/etc/motd"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file("/dotfiles/guest/bashrc");
+        fs.set_stat("/dotfiles/guest/bashrc", Stat { uid: 1000, gid: 1000, mode: 0o644 });
+        fs.add_file("/dotfiles/shared/motd");
+        fs.set_stat("/dotfiles/shared", Stat { uid: 0, gid: 0, mode: 0o777 });
+        fs.set_stat("/dotfiles/vimrc", Stat { uid: 0, gid: 1000, mode: 0o664 });
+        fs.set_stat("/", Stat { uid: 0, gid: 0, mode: 0o1777 });
+
+        let options = LinkageOptions { allow_unsafe_sources: true, ..options(Mode::Strict) };
+        let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+
+        assert_eq!(
+            find_unsafe_sources(&fs, &plan.links),
+            vec![PathBuf::from("/dotfiles/shared"), PathBuf::from("/dotfiles/vimrc")]
+        );
+    }
+
+    #[test]
+    fn dry_mode_changes_nothing() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+
+        let result = link(&fs, &dotfiles, Mode::Dry);
+
+        match result {
+            LinkageResult::DryResult(planned_links, _) => {
+                let actions: Vec<_> = planned_links.into_iter().flat_map(|l| l.actions).collect();
+                assert_eq!(
+                    actions[0],
+                    Action::CreateParent { path: PathBuf::from("/home/link/.config") }
+                );
+                assert_eq!(actions.len(), 3);
             }
+            result => panic!("Unexpected result: {:?}", result),
         }
-        TargetState::Linked(_) => LinkResult::Existed,
+        assert_eq!(fs.node("/home/link/.vimrc"), None);
+        assert_eq!(fs.node("/home/link/.config"), None);
     }
-}
 
-fn expand_dest(dest: &Path) -> PathBuf {
-    if dest.starts_with("~/") {
-        let home_dir = dirs::home_dir().unwrap();
+    #[test]
+    fn strict_mode_links_absent_targets() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+
+        let result = link(&fs, &dotfiles, Mode::Strict);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+                ("/home/link/.vimrc".to_string(), "Success".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
+        assert_eq!(fs.node("/home/link/.config"), Some(MemoryNode::Dir));
+        assert_eq!(fs.node("/home/link/.config/nvim"), symlink("/dotfiles/nvim"));
+    }
 
-        let home_dir_components: Vec<_> = home_dir.components().collect();
-        let start_components = &home_dir_components[..];
+    #[test]
+    fn strict_mode_refuses_existing_targets() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
 
-        let dest_components: Vec<_> = dest.components().collect();
-        let end_components = &dest_components[1..];
+        let result = link(&fs, &dotfiles, Mode::Strict);
 
-        let new_path_buf: path::PathBuf =
-            [start_components, end_components].concat().iter().collect();
+        assert_eq!(blocking_targets(&result), vec![("/home/link/.vimrc".to_string(), TargetState::AlienNode)]);
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/home/link/.config/nvim"), None);
+    }
 
-        new_path_buf
-    } else {
-        dest.to_path_buf()
+    fn blocking_targets(result: &LinkageResult) -> Vec<(String, TargetState)> {
+        match result {
+            LinkageResult::PreconditionFailed(Precondition::TargetsExist(link_tasks)) => link_tasks
+                .iter()
+                .map(|task| (task.target.display().to_string(), task.target_state))
+                .collect(),
+            result => panic!("Precondition did not fail: {:?}", result),
+        }
     }
-}
 
-fn create_parent(dest: &Path) -> io::Result<()> {
-    let target_dir = dest.parent().unwrap();
+    #[test]
+    fn strictness_allows_linked_targets_and_scopes_tags() {
+        let dotfiles = dotfiles(&LINKFILE.replacen("source = \"vimrc\"", "source = \"vimrc\"\ntag = \"vim\"", 1));
+        let fs = filesystem();
+        fs.add_symlink("/home/link/.vimrc", "/dotfiles/vimrc");
+        fs.add_dir("/home/link/.config/nvim");
+        let strict = |strictness: Strictness| {
+            let options = LinkageOptions { tags: Tags::new(["vim"]), strictness, ..options(Mode::Strict) };
+            apply_in(&fs, &decryptor(), &dotfiles, plan_in(&fs, &dotfiles, &options).unwrap())
+        };
 
-    if !target_dir.exists() {
-        fs::create_dir_all(target_dir)?
-    } else if !target_dir.is_dir() {
-        // TODO: Replace error type
-        return Err(io::Error::new(
-            io::ErrorKind::Other,
-            "Parent path for the target is not directory!",
+        let result = strict(Strictness::default());
+        assert_eq!(
+            blocking_targets(&result),
+            vec![
+                ("/home/link/.vimrc".to_string(), TargetState::Linked),
+                ("/home/link/.config/nvim".to_string(), TargetState::AlienNode),
+            ]
+        );
+
+        let result = strict(Strictness { allow_linked: true, tags: Vec::new() });
+        assert_eq!(
+            blocking_targets(&result),
+            vec![("/home/link/.config/nvim".to_string(), TargetState::AlienNode)]
+        );
+
+        let result = strict(Strictness { allow_linked: false, tags: vec!["vim".to_string()] });
+        assert_eq!(blocking_targets(&result), vec![("/home/link/.vimrc".to_string(), TargetState::Linked)]);
+
+        let result = strict(Strictness { allow_linked: true, tags: vec!["vim".to_string()] });
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.vimrc".to_string(), "Existed".to_string()),
+                ("/home/link/.config/nvim".to_string(), "Skipped".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.config/nvim"), Some(MemoryNode::Dir));
+    }
+
+    #[test]
+    fn lazy_mode_keeps_existing_targets() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+        fs.add_symlink("/home/link/.config/nvim", "/dotfiles/nvim");
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(results(&result), vec![]);
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.1"), None);
+    }
+
+    #[test]
+    fn lazy_mode_normalizes_indirect_links_on_request() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+        fs.add_symlink("/home/link/dotfiles", "/dotfiles");
+        fs.add_symlink("/home/link/.vimrc", "dotfiles/vimrc");
+        // Relative links straight to the source are correct already
+        fs.add_symlink("/home/link/.config/nvim", "../../../dotfiles/nvim");
+
+        let options = LinkageOptions { normalize_links: true, ..options(Mode::Lazy) };
+        let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+
+        assert_eq!(results(&result), vec![("/home/link/.vimrc".to_string(), "Normalized".to_string())]);
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
+        assert_eq!(fs.node("/home/link/.config/nvim"), symlink("../../../dotfiles/nvim"));
+    }
+
+    #[test]
+    fn force_mode_backs_up_existing_targets() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+        fs.add_file("/home/link/.vimrc.bak.1");
+        fs.add_file("/home/link/.config/nvim/init.lua");
+
+        let result = link(&fs, &dotfiles, Mode::Force);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+                ("/home/link/.vimrc".to_string(), "Success".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.1"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/home/link/.vimrc.bak.2"), Some(MemoryNode::File));
+        assert_eq!(
+            fs.node("/home/link/.config/nvim.bak.1/init.lua"),
+            Some(MemoryNode::File)
+        );
+    }
+
+    #[test]
+    fn force_mode_moves_backups_to_backup_dir() {
+        let dotfiles = dotfiles(&format!(
+            "[meta]\nbackup_dir = \"backups\"\nbackup_naming = \"none\"\n{}",
+            LINKFILE
         ));
+        let fs = filesystem();
+        fs.add_symlink("/home/link/.vimrc", "/etc/vimrc");
+
+        let options = LinkageOptions {
+            target_root: TargetRoot { home: Some(PathBuf::from("/home/link")), destdir: None },
+            ..options(Mode::Force)
+        };
+        let plan = plan_in(&fs, &dotfiles, &options).unwrap();
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+
+        assert_eq!(results(&result).len(), 2);
+        let run = backup_run(&fs, "/home/link/backups");
+        assert_eq!(fs.node(run.join("home/link/.vimrc.bak")), symlink("/etc/vimrc"));
+        assert_eq!(fs.node("/home/link/.vimrc"), symlink("/dotfiles/vimrc"));
     }
 
-    Ok(())
-}
+    #[test]
+    fn apply_skips_targets_changed_since_planning() {
+        let dotfiles = dotfiles(LINKFILE);
+        let fs = filesystem();
+
+        let plan = plan_in(&fs, &dotfiles, &options(Mode::Force)).unwrap();
+        fs.add_file("/home/link/.vimrc");
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+                ("/home/link/.vimrc".to_string(), "StateChanged(AlienNode)".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+    }
 
-#[cfg(target_family = "unix")]
-fn platform_link(source: &Path, dest: &Path) -> io::Result<()> {
-    create_parent(dest)?;
-    os::unix::fs::symlink(source, dest)
-}
+    #[test]
+    fn io_errors_are_reported_per_link() {
+        let dotfiles = dotfiles(LINKFILE);
+        let mut fs = filesystem();
+        fs.add_file("/home/link/.vimrc");
+
+        let plan = plan_in(&fs, &dotfiles, &options(Mode::Force)).unwrap();
+        fs.fail("/home/link/.vimrc.bak.1", io::ErrorKind::PermissionDenied);
+        fs.fail("/home/link/.config", io::ErrorKind::PermissionDenied);
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+
+        let results = results(&result);
+        assert_eq!(results.len(), 2);
+        for (_, result) in results {
+            assert!(result.contains("PermissionDenied"), "{}", result);
+        }
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::File));
+    }
 
-#[cfg(target_family = "windows")]
-fn platform_link(source: &Path, dest: &Path) -> io::Result<()> {
-    create_parent(dest)?;
+    const ENCRYPTED: &str = r#"
+        [[link]]
+        source = "netrc.age"
+        target = "/home/link/.netrc"
+        encryption = "age"
+    "#;
+
+    #[test]
+    fn file_mode_of_copy_is_not_applied_to_parents() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "netrc.age"
+            target = "/home/link/.config/netrc/netrc"
+            encryption = "age"
+            mode = "0600"
+            "#,
+        );
+        let fs = filesystem();
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor().encrypt(b"machine example.com"));
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(results(&result), vec![("/home/link/.config/netrc/netrc".to_string(), "Success".to_string())]);
+        for directory in ["/home/link/.config", "/home/link/.config/netrc"] {
+            assert_eq!(fs.node_stat(directory), Some(Stat { uid: 0, gid: 0, mode: 0o755 }));
+        }
+        assert_eq!(fs.node_stat("/home/link/.config/netrc/netrc"), Some(Stat { uid: 0, gid: 0, mode: 0o600 }));
+    }
 
-    if source.is_dir() {
-        os::windows::fs::symlink_dir(source, dest)
-    } else {
-        os::windows::fs::symlink_file(source, dest)
+    #[test]
+    fn encrypted_source_is_decrypted_into_private_copy() {
+        let dotfiles = dotfiles(ENCRYPTED);
+        let fs = filesystem();
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor().encrypt(b"machine example.com"));
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(results(&result), vec![("/home/link/.netrc".to_string(), "Success".to_string())]);
+        assert_eq!(fs.node("/home/link/.netrc"), Some(MemoryNode::File));
+        assert_eq!(fs.content("/home/link/.netrc"), Some(b"machine example.com".to_vec()));
+        assert_eq!(fs.node_stat("/home/link/.netrc").map(|stat| stat.mode), Some(0o600));
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+        assert_eq!(results(&result), vec![]);
+    }
+
+    #[test]
+    fn decrypt_failure_is_reported_per_link() {
+        let dotfiles = dotfiles(ENCRYPTED);
+        let fs = filesystem();
+        let ciphertext = FakeDecryptor::new(b"other").encrypt(b"machine example.com");
+        fs.add_file_content("/dotfiles/netrc.age", &ciphertext);
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        let results = results(&result);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].1.contains("no matching key"), "{}", results[0].1);
+        assert_eq!(fs.node("/home/link/.netrc"), None);
+    }
+
+    #[test]
+    fn outdated_copy_is_replaced_only_by_force() {
+        let dotfiles = dotfiles(ENCRYPTED);
+        let fs = filesystem();
+        fs.add_file_content("/home/link/.netrc", b"machine old.example.com");
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor().encrypt(b"machine example.com"));
+
+        let link_tasks = examine_in(&fs, &dotfiles, &options(Mode::Dry)).unwrap();
+        assert_eq!(link_tasks[0].target_state, TargetState::Outdated);
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+        assert_eq!(results(&result), vec![]);
+        assert_eq!(fs.content("/home/link/.netrc"), Some(b"machine old.example.com".to_vec()));
+
+        let result = link(&fs, &dotfiles, Mode::Force);
+        assert_eq!(results(&result), vec![("/home/link/.netrc".to_string(), "Success".to_string())]);
+        assert_eq!(fs.content("/home/link/.netrc"), Some(b"machine example.com".to_vec()));
+        assert_eq!(fs.content("/home/link/.netrc.bak.1"), Some(b"machine old.example.com".to_vec()));
+    }
+
+    #[test]
+    fn copy_edited_in_place_is_modified() {
+        let dotfiles = dotfiles(ENCRYPTED);
+        let fs = filesystem();
+        fs.add_file_content("/dotfiles/netrc.age", &decryptor().encrypt(b"machine example.com"));
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+        assert_eq!(results(&result), vec![("/home/link/.netrc".to_string(), "Success".to_string())]);
+
+        // Newer than the source, only the content tells the edit
+        fs.add_file_content("/home/link/.netrc", b"machine edited.example.com");
+        let deployed = HashMap::from([(PathBuf::from("/home/link/.netrc"), drift::hash(b"machine example.com"))]);
+        let options = |mode| LinkageOptions { deployed: deployed.clone(), ..options(mode) };
+
+        let link_tasks = examine_in(&fs, &dotfiles, &options(Mode::Dry)).unwrap();
+        assert_eq!(link_tasks[0].target_state, TargetState::Modified);
+        assert!(link_tasks[0].is_conflict());
+
+        let plan = plan_in(&fs, &dotfiles, &options(Mode::Lazy)).unwrap();
+        assert_eq!(results(&apply_in(&fs, &decryptor(), &dotfiles, plan)), vec![]);
+        assert_eq!(fs.content("/home/link/.netrc"), Some(b"machine edited.example.com".to_vec()));
+
+        let plan = plan_in(&fs, &dotfiles, &options(Mode::Force)).unwrap();
+        let result = apply_in(&fs, &decryptor(), &dotfiles, plan);
+        assert_eq!(results(&result), vec![("/home/link/.netrc".to_string(), "Success".to_string())]);
+        assert_eq!(fs.content("/home/link/.netrc"), Some(b"machine example.com".to_vec()));
+        assert_eq!(fs.content("/home/link/.netrc.bak.1"), Some(b"machine edited.example.com".to_vec()));
+    }
+
+    const HOOKED: &str = r#"
+        [meta.hooks]
+        post_link = "touch meta_post_link"
+
+        [[link]]
+        source = "vimrc"
+        target = "/home/link/.vimrc"
+        hooks.pre_link = 'printf %s\\n "$RINKU_HOOK" "$RINKU_MODE" "$RINKU_ROOT" "$RINKU_SOURCE" "$RINKU_TARGET" > vimrc.env'
+        hooks.post_link = "touch vimrc_post_link"
+
+        [[link]]
+        source = "nvim"
+        target = "/home/link/.config/nvim"
+        hooks.post_link = "touch nvim_post_link"
+    "#;
+
+    /// Hooks are started in the linkfile directory, it has to exist on the disk
+    fn hooked(root: &Path, content: &str) -> (Dotfiles, MemoryFileSystem) {
+        let dotfiles = Dotfiles { root: root.to_path_buf(), ..dotfiles(content) };
+        let fs = MemoryFileSystem::new();
+        fs.add_file(root.join("vimrc"));
+        fs.add_dir(root.join("nvim"));
+        fs.add_dir("/home/link");
+        (dotfiles, fs)
+    }
+
+    #[test]
+    fn hooks_receive_linkage_environment() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (dotfiles, fs) = hooked(root, HOOKED);
+
+        link(&fs, &dotfiles, Mode::Lazy);
+
+        let env = std::fs::read_to_string(root.join("vimrc.env")).unwrap();
+        let expected = format!(
+            "pre_link\nlazy\n{}\n{}\n/home/link/.vimrc\n",
+            root.display(),
+            root.join("vimrc").display(),
+        );
+        assert_eq!(env, expected);
+    }
+
+    #[test]
+    fn post_link_hooks_run_only_on_success() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (dotfiles, fs) = hooked(root, HOOKED);
+        fs.add_symlink("/home/link/.vimrc", root.join("vimrc"));
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(results(&result), vec![("/home/link/.config/nvim".to_string(), "Success".to_string())]);
+        assert!(root.join("nvim_post_link").exists());
+        assert!(root.join("meta_post_link").exists());
+        assert!(!root.join("vimrc_post_link").exists());
+        assert!(!root.join("vimrc.env").exists());
+
+        std::fs::remove_file(root.join("meta_post_link")).unwrap();
+        let result = link(&fs, &dotfiles, Mode::Force);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Existed".to_string()),
+                ("/home/link/.vimrc".to_string(), "Existed".to_string()),
+            ]
+        );
+        assert!(!root.join("meta_post_link").exists());
+    }
+
+    #[test]
+    fn failing_pre_link_hook_skips_its_link() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let env_hook = r#"printf %s\\n "$RINKU_HOOK" "$RINKU_MODE" "$RINKU_ROOT" "$RINKU_SOURCE" "$RINKU_TARGET" > vimrc.env"#;
+        let (dotfiles, fs) = hooked(root, &HOOKED.replace(env_hook, "exit 1"));
+
+        let result = link(&fs, &dotfiles, Mode::Lazy);
+
+        assert_eq!(
+            results(&result),
+            vec![
+                ("/home/link/.config/nvim".to_string(), "Success".to_string()),
+                ("/home/link/.vimrc".to_string(), "Skipped".to_string()),
+            ]
+        );
+        assert_eq!(fs.node("/home/link/.vimrc"), None);
+        assert!(!root.join("vimrc_post_link").exists());
+        assert!(root.join("nvim_post_link").exists());
+    }
+
+    #[test]
+    fn dry_mode_runs_no_hooks() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let (dotfiles, fs) = hooked(root, HOOKED);
+
+        let result = link(&fs, &dotfiles, Mode::Dry);
+
+        match result {
+            LinkageResult::DryResult(planned_links, hooks) => {
+                assert_eq!(hooks.post_link.as_deref(), Some("touch meta_post_link"));
+                assert!(planned_links.iter().all(|planned_link| planned_link.task.hooks.post_link.is_some()));
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 0);
+        assert_eq!(fs.node("/home/link/.vimrc"), None);
     }
 }
@@ -1,8 +1,87 @@
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
 use strum::{Display, EnumString};
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, Display, EnumString)]
+use crate::error::Error;
+use crate::ignore;
+
+/// Conventional names of the linkfile at the top of the dotfiles directory
+pub const LINKFILE_NAMES: [&str; 8] = [
+    "dotfiles.toml",
+    "rinku.toml",
+    "dotfiles.yaml",
+    "rinku.yaml",
+    "dotfiles.yml",
+    "rinku.yml",
+    "dotfiles.json",
+    "rinku.json",
+];
+
+/// Syntax of the linkfile, all of them describe the same `Linkfile`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Format by the extension of the file, TOML for unknown ones
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+
+    pub fn parse(self, content: &str) -> Result<Linkfile, Error> {
+        let linkfile: Linkfile = match self {
+            Format::Toml => toml::from_str(content)?,
+            Format::Yaml => serde_yaml::from_str(content).map_err(|err| {
+                let location = err.location().map(|location| (location.line(), location.column()));
+                Error::YamlParse(annotate(content, location, &err.to_string()))
+            })?,
+            Format::Json => serde_json::from_str(content).map_err(|err| {
+                let location = Some((err.line(), err.column())).filter(|(line, _)| *line > 0);
+                Error::JsonParse(annotate(content, location, &err.to_string()))
+            })?,
+        };
+        linkfile.validate()?;
+        Ok(linkfile)
+    }
+}
+
+/// Renders the message with the offending line of the content, the same
+/// way TOML errors are shown
+fn annotate(content: &str, location: Option<(usize, usize)>, message: &str) -> String {
+    let (line, column) = match location {
+        Some(location) => location,
+        None => return message.to_string(),
+    };
+    let text = content.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+    let gutter = " ".repeat(line.to_string().len());
+
+    format!(
+        "line {}, column {}\n{} |\n{} | {}\n{} | {}^\n{}",
+        line,
+        column,
+        gutter,
+        line,
+        text,
+        gutter,
+        " ".repeat(column.saturating_sub(1)),
+        message
+    )
+}
+
+/// Platform family of the platform specific targets
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize, JsonSchema, Display, EnumString)]
 #[strum(serialize_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum Environment {
@@ -10,33 +89,149 @@ pub enum Environment {
     Windows,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+/// One or several paths of the link, `~/` is expanded
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum Destination {
     Single(String),
     Multi(Vec<String>),
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+/// Destination for every platform or per platform family
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema)]
 #[serde(untagged)]
 pub enum Target {
     Unified(Destination),
     Platform(HashMap<Environment, Destination>),
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+/// Naming scheme of the targets moved aside by the force mode
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupNaming {
+    /// `<name>.bak.<#>`
+    #[default]
+    Numbered,
+    /// `<name>.bak.<%Y%m%dT%H%M%S>`
+    Timestamp,
+    /// `<name>.bak`, only single backup is kept
+    None,
+}
+
+/// Shell commands executed around linking, see `hooks` module
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema, Default)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre_link: Option<String>,
+    pub post_link: Option<String>,
+    pub post_unlink: Option<String>,
+}
+
+/// Format of the encrypted sources
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize, JsonSchema, Display)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Encryption {
+    Age,
+    Gpg,
+}
+
+/// What happens to the target occupied by something else
+///
+/// Overrides the treatment of such targets by the mode. The link setting
+/// takes precedence over everything, the one in `[meta]` only over the
+/// default mode of the command.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize, JsonSchema, Display)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OnConflict {
+    /// Leaves the target as is
+    Skip,
+    /// Moves the target aside before linking
+    Backup,
+    /// Deletes the target before linking
+    Replace,
+    /// Moves the target in place of the source, which is backed up outside
+    /// of the linkfile directory
+    Adopt,
+    /// Refuses the whole linkage
+    Fail,
+}
+
+/// Owner and permissions of the nodes created for the link
+///
+/// Owner and group apply to the link itself and to the parent directories
+/// it required. `mode` applies to decrypted copies, `parent_mode` to the
+/// directories, permissions of symlinks are not used.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema, Default)]
+#[serde(default)]
+pub struct Ownership {
+    /// User name or uid
+    pub owner: Option<String>,
+    /// Group name or gid
+    pub group: Option<String>,
+    /// Octal permission bits of the decrypted copy, e.g. `"0600"`
+    pub mode: Option<String>,
+    /// Octal permission bits of the created parent directories, they keep
+    /// the default ones when not set
+    pub parent_mode: Option<String>,
+}
+
+/// Single `source -> target` entry
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema)]
 pub struct Link {
+    /// Path relative to the linkfile directory, wildcards link every match
+    /// under the target directory
     pub source: String,
     pub target: Target,
+    /// Link is enabled only when the tag is active
     pub tag: Option<String>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(flatten)]
+    pub ownership: Ownership,
+    /// Create missing parent directories of the target, enabled by default
+    pub create_parents: Option<bool>,
+    /// Source is encrypted, target gets a private decrypted copy instead
+    /// of the symlink
+    pub encryption: Option<Encryption>,
+    /// Patterns in gitignore syntax the matches of the wildcard source are
+    /// left out by, relative to the source directory, other sources may
+    /// not have them
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Treatment of the occupied target regardless of the mode
+    pub on_conflict: Option<OnConflict>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Default)]
+/// Settings of the whole linkfile
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema, Default)]
+#[serde(default)]
 pub struct Meta {
+    /// Tags active when none are given explicitly
     pub default_tags: Vec<String>,
+    pub hooks: Hooks,
+    /// Directory the backups are kept in instead of next to the targets,
+    /// relative to the home directory, every run gets a timestamped one inside
+    pub backup_dir: Option<String>,
+    pub backup_naming: BackupNaming,
+    /// Key file for age or GnuPG home directory for gpg, relative to the
+    /// linkfile directory
+    pub identity: Option<String>,
+    /// Keep the deployed content of the copies, decrypted secrets included,
+    /// for the three-way merge of `drift --merge`, only hashes are kept
+    /// otherwise
+    pub merge_base: bool,
+    /// Patterns in gitignore syntax applied to the matches of every wildcard
+    /// source, relative to the linkfile directory
+    pub ignore: Vec<String>,
+    /// Treatment of the occupied targets in the default mode of the
+    /// command, a mode given with `-m` and links override it
+    pub on_conflict: Option<OnConflict>,
 }
 
-#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+/// Configuration of rinku, usually `dotfiles.toml`
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, JsonSchema)]
 pub struct Linkfile {
     #[serde(rename = "meta", default)]
     pub meta: Meta,
@@ -44,10 +239,73 @@ pub struct Linkfile {
     pub links: Vec<Link>,
 }
 
+impl Linkfile {
+    /// Rejects settings that would be ignored silently
+    fn validate(&self) -> Result<(), Error> {
+        let errors: Vec<(PathBuf, io::Error)> = self
+            .links
+            .iter()
+            .filter(|link| !link.exclude.is_empty() && !ignore::is_glob(&link.source))
+            .map(|link| {
+                let message = "exclude applies only to sources with wildcards";
+                (PathBuf::from(&link.source), io::Error::new(io::ErrorKind::InvalidInput, message))
+            })
+            .collect();
+
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(Error::LinkfileContent(errors)),
+        }
+    }
+}
+
+/// JSON Schema of the linkfile, suitable for every format
+pub fn schema() -> String {
+    let schema = schemars::schema_for!(Linkfile);
+    serde_json::to_string_pretty(&schema).expect("Schema is serializable") + "\n"
+}
+
+/// Linkfile read from the disk along with its location
+#[derive(Clone, Debug)]
+pub struct Dotfiles {
+    /// Canonical path of the linkfile
+    pub linkfile_path: PathBuf,
+    /// Directory of the linkfile, link sources are relative to it
+    pub root: PathBuf,
+    pub linkfile: Linkfile,
+    pub format: Format,
+}
+
+impl Dotfiles {
+    /// Loads the linkfile in the format given by its extension
+    pub fn load(path: &Path) -> Result<Dotfiles, Error> {
+        Dotfiles::load_as(path, Format::from_path(path))
+    }
+
+    pub fn load_as(path: &Path, format: Format) -> Result<Dotfiles, Error> {
+        let linkfile_path = path.canonicalize().map_err(Error::BadLinkfile)?;
+        let root = linkfile_path
+            .parent()
+            .ok_or(Error::BadLinkfilePath)?
+            .to_path_buf();
+
+        let content = fs::read_to_string(&linkfile_path).map_err(Error::BadLinkfile)?;
+        let linkfile = format.parse(&content)?;
+
+        Ok(Dotfiles { linkfile_path, root, linkfile, format })
+    }
+
+    /// Reads the linkfile again, e.g. after it was changed
+    pub fn reload(&self) -> Result<Dotfiles, Error> {
+        Dotfiles::load_as(&self.linkfile_path, self.format)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Destination, Environment, Link, Linkfile, Target, Meta};
+    use super::{Destination, Encryption, Environment, Format, Hooks, Link, Linkfile, Meta, Ownership, Target};
     use std::collections::HashMap;
+    use std::path::Path;
 
     #[test]
     fn smoke_linkfile() {
@@ -76,9 +334,161 @@ mod tests {
                         )
                     ])),
                     tag: None,
+                    hooks: Hooks::default(),
+                    ownership: Ownership::default(),
+                    create_parents: None,
+                    encryption: None,
+                    exclude: Vec::new(),
+                    on_conflict: None,
                 }],
-                meta: Meta { default_tags: vec![] },
+                meta: Meta::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn hooks_linkfile() {
+        const INPUT: &str = r#"
+            [meta.hooks]
+            post_link = "fish -c fish_update_completions"
+
+            [[link]]
+            source = "nvim"
+            target = "~/.config/nvim"
+            hooks.post_link = "nvim --headless +PlugInstall +qa"
+        "#;
+
+        let linkfile: Linkfile = toml::from_str(INPUT).unwrap();
+
+        assert_eq!(
+            linkfile.meta.hooks,
+            Hooks {
+                post_link: Some("fish -c fish_update_completions".to_string()),
+                ..Hooks::default()
+            }
+        );
+        assert_eq!(
+            linkfile.links[0].hooks,
+            Hooks {
+                post_link: Some("nvim --headless +PlugInstall +qa".to_string()),
+                ..Hooks::default()
             }
         );
     }
+
+    #[test]
+    fn ownership_linkfile() {
+        const INPUT: &str = r#"
+            [[link]]
+            source = "sshd_config"
+            target = "/etc/ssh/sshd_config.d/rinku.conf"
+            owner = "root"
+            mode = "0700"
+        "#;
+
+        let linkfile: Linkfile = toml::from_str(INPUT).unwrap();
+
+        assert_eq!(
+            linkfile.links[0].ownership,
+            Ownership {
+                owner: Some("root".to_string()),
+                group: None,
+                mode: Some("0700".to_string()),
+                parent_mode: None,
+            }
+        );
+    }
+
+    #[test]
+    fn encryption_linkfile() {
+        const INPUT: &str = r#"
+            [meta]
+            identity = "~/.config/age/key.txt"
+
+            [[link]]
+            source = "netrc.age"
+            target = "~/.netrc"
+            encryption = "age"
+        "#;
+
+        let linkfile: Linkfile = toml::from_str(INPUT).unwrap();
+
+        assert_eq!(linkfile.meta.identity.as_deref(), Some("~/.config/age/key.txt"));
+        assert_eq!(linkfile.links[0].encryption, Some(Encryption::Age));
+    }
+
+    #[test]
+    fn formats_are_equivalent() {
+        const TOML: &str = r#"
+            [meta]
+            default_tags = ["work"]
+
+            [[link]]
+            source = "vimrc"
+            target.unix = ["~/.vimrc", "~/.config/nvim/init.vim"]
+            target.windows = "vimfiles/vimrc"
+            mode = "0600"
+        "#;
+        const YAML: &str = r#"
+            meta:
+              default_tags: [work]
+            link:
+              - source: vimrc
+                target:
+                  unix: ["~/.vimrc", "~/.config/nvim/init.vim"]
+                  windows: vimfiles/vimrc
+                mode: "0600"
+        "#;
+        const JSON: &str = r#"{
+            "meta": { "default_tags": ["work"] },
+            "link": [{
+                "source": "vimrc",
+                "target": {
+                    "unix": ["~/.vimrc", "~/.config/nvim/init.vim"],
+                    "windows": "vimfiles/vimrc"
+                },
+                "mode": "0600"
+            }]
+        }"#;
+
+        let linkfile = Format::Toml.parse(TOML).unwrap();
+        assert_eq!(Format::Yaml.parse(YAML).unwrap(), linkfile);
+        assert_eq!(Format::Json.parse(JSON).unwrap(), linkfile);
+    }
+
+    #[test]
+    fn format_errors_show_location() {
+        let yaml = "link:\n  - source: vimrc\n    target: [\n";
+        let message = Format::Yaml.parse(yaml).unwrap_err().to_string();
+        assert!(message.contains("line 4"), "{}", message);
+
+        let json = "{\n  \"link\": [{ \"source\": 1 }]\n}";
+        let message = Format::Json.parse(json).unwrap_err().to_string();
+        assert!(message.contains("line 2, column"), "{}", message);
+        assert!(message.contains("2 |   \"link\""), "{}", message);
+    }
+
+    #[test]
+    fn exclude_of_plain_source_is_rejected() {
+        let linkfile = "[[link]]\nsource = 'nvim'\ntarget = '~/.config/nvim'\nexclude = ['*.swp']\n";
+        let message = Format::Toml.parse(linkfile).unwrap_err().to_string();
+        assert!(message.contains("nvim:\texclude applies only to sources with wildcards"), "{}", message);
+
+        assert!(Format::Toml.parse(&linkfile.replace("'nvim'", "'nvim/*'")).is_ok());
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(Format::from_path(Path::new("rinku.yml")), Format::Yaml);
+        assert_eq!(Format::from_path(Path::new("rinku.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("dotfiles")), Format::Toml);
+    }
+
+    #[test]
+    fn schema_is_up_to_date() {
+        assert!(
+            super::schema() == include_str!("../schema/linkfile.schema.json"),
+            "Schema is outdated, run `rinku schema > schema/linkfile.schema.json`"
+        );
+    }
 }
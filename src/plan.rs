@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::filesystem::Attributes;
+use crate::linker::{LinkTask, Mode, Strictness, TargetRoot};
+use crate::linkfile::Encryption;
+
+/// Single filesystem change, executed in the order of planning
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    CreateParent { path: PathBuf },
+    Backup { path: PathBuf },
+    /// Moves the source aside before `Adopt`, never into the linkfile
+    /// directory
+    BackupSource { path: PathBuf },
+    Remove { path: PathBuf },
+    /// Removes the node with everything inside
+    Delete { path: PathBuf },
+    /// Moves the target in place of the source
+    Adopt { source: PathBuf, target: PathBuf },
+    CreateSymlink { source: PathBuf, target: PathBuf },
+    Decrypt { source: PathBuf, target: PathBuf, encryption: Encryption },
+    SetAttributes { path: PathBuf, attributes: Attributes },
+}
+
+/// Link task together with the actions planned for it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlannedLink {
+    pub task: LinkTask,
+    pub actions: Vec<Action>,
+}
+
+/// Outcome of `linker::plan`, may be stored for review and applied later
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Plan {
+    /// Canonical path of the linkfile the plan was made for
+    pub linkfile: PathBuf,
+    pub mode: Mode,
+    /// Mode was given with `-m`, see `ConflictPolicy::resolve`
+    #[serde(default)]
+    pub explicit_mode: bool,
+    #[serde(default)]
+    pub target_root: TargetRoot,
+    #[serde(default)]
+    pub strictness: Strictness,
+    pub links: Vec<PlannedLink>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Plan, Error> {
+        let content = fs::read_to_string(path).map_err(Error::PlanFile)?;
+        serde_json::from_str(&content).map_err(|e| Error::PlanFile(io::Error::from(e)))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let content =
+            serde_json::to_string_pretty(self).map_err(|e| Error::PlanFile(io::Error::from(e)))?;
+        fs::write(path, content).map_err(Error::PlanFile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Action;
+    use std::path::PathBuf;
+
+    #[test]
+    fn action_json() {
+        let action = Action::CreateSymlink {
+            source: PathBuf::from("/dotfiles/vimrc"),
+            target: PathBuf::from("/home/link/.vimrc"),
+        };
+
+        let json = serde_json::to_string(&action).unwrap();
+        assert_eq!(
+            json,
+            r#"{"action":"create_symlink","source":"/dotfiles/vimrc","target":"/home/link/.vimrc"}"#
+        );
+        assert_eq!(serde_json::from_str::<Action>(&json).unwrap(), action);
+    }
+}
@@ -1,80 +1,929 @@
-use crate::linker::{LinkResult, LinkState, LinkTask, LinkageResult, TargetState};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::backup::{BackupResult, BackupState};
+use crate::drift::{DriftState, DriftStatus, Merge};
+use crate::filesystem::Attributes;
+use crate::git::Change;
+use crate::hooks::{HookKind, HookRun, HookStatus};
+use crate::ignore::Ignored;
+use crate::linker::{LinkResult, LinkState, LinkTask, LinkageResult, Precondition, TargetState};
+use crate::linkfile::Hooks;
+use crate::plan::{Action, Plan, PlannedLink};
+use crate::prune::{DirectoryState, PruneResult, PruneState};
 use colored::*;
 
-pub fn present_result(linkage_result: &LinkageResult) {
-    match linkage_result {
-        LinkageResult::DryResult(link_tasks) => present_dry_result(&link_tasks),
-        LinkageResult::PreconditionFailed(reason) => {
-            println!(
-                "Precondition failed: {}. Try to run in the dry mode.",
-                reason
-            );
+/// When the output is colored
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum ColorChoice {
+    /// Colored when the output is a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Whether the output should be colored
+///
+/// `CLICOLOR_FORCE` other than `0` colors the output in the auto mode even
+/// when it is not a terminal, non-empty `NO_COLOR` disables colors.
+pub fn color_enabled(choice: ColorChoice, var: impl Fn(&str) -> Option<String>, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let is_set = |name| var(name).is_some_and(|value| !value.is_empty());
+            if is_set("CLICOLOR_FORCE") && var("CLICOLOR_FORCE").as_deref() != Some("0") {
+                true
+            } else if is_set("NO_COLOR") {
+                false
+            } else {
+                is_terminal
+            }
         }
-        LinkageResult::Completed(link_states) => present_completed(&link_states),
     }
 }
 
-fn present_dry_result(link_tasks: &Vec<LinkTask>) {
+/// Enables or disables colors of everything printed afterwards
+pub fn set_colors(enabled: bool) {
+    colored::control::set_override(enabled);
+}
+
+/// How lines of the links are grouped
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum Grouping {
+    Tag,
+    /// Top level directory of the source in the linkfile directory
+    Package,
+}
+
+/// Status of the link line, the ones of the targets and of the linkage
+/// results
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum Status {
+    Todo,
+    Alien,
+    Dangling,
+    Linked,
+    Indirect,
+    Outdated,
+    Modified,
+    Missing,
+    Existed,
+    Skipped,
+    Success,
+    Normalized,
+    NoParent,
+    Changed,
+    Error,
+}
+
+impl Status {
+    /// Whether the line shown with the status text is of this status
+    fn is_of(self, status: &str) -> bool {
+        self.to_possible_value().is_some_and(|value| value.get_name() == status_key(status))
+    }
+}
+
+/// Which lines of the links are shown
+///
+/// Summary always counts every link, filters only hide lines.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// Statuses to show, all when empty
+    pub only: Vec<Status>,
+    /// Shows only problems
+    pub quiet: bool,
+    pub group_by: Option<Grouping>,
+    /// Linkfile directory, packages are its top level entries
+    pub root: PathBuf,
+    /// Shows targets as a tree of directories
+    pub tree: bool,
+    /// Home directory shown as `~` in the tree
+    pub home: Option<PathBuf>,
+    /// Width to fit the tree lines in, not limited when unknown
+    pub width: Option<usize>,
+}
+
+/// Line of the single link with the details below it
+struct Row<'a> {
+    status: ColoredString,
+    task: &'a LinkTask,
+    problem: bool,
+    details: Vec<String>,
+}
+
+/// Name of the status in the summary, the same as of `Status`
+fn status_key(status: &str) -> String {
+    status.to_lowercase().replace(' ', "_")
+}
+
+fn group_key(task: &LinkTask, grouping: Grouping, root: &Path) -> String {
+    match grouping {
+        Grouping::Tag => task.tag.clone().unwrap_or_else(|| "untagged".to_string()),
+        Grouping::Package => task
+            .source
+            .strip_prefix(root)
+            .ok()
+            .and_then(|source| source.iter().next())
+            .map(|package| package.to_string_lossy().to_string())
+            .unwrap_or_else(|| task.source.display().to_string()),
+    }
+}
+
+/// Prints the rows allowed by the report and returns counts of all rows
+/// per status in the order of appearance
+fn present_rows(
+    out: &mut dyn Write,
+    mut rows: Vec<Row>,
+    width: usize,
+    report: &Report,
+) -> io::Result<Vec<(String, usize)>> {
     let arrow = "->".magenta().bold();
-    let to_link = "TODO".yellow().bold();
-    let alien_file = "ALIEN".red().bold();
-    let alien_link = "ALIEN".red().bold();
-    let linked = "LINKED".green().bold();
 
-    let print_status = |status, source: &str, target: &str| {
-        println!("{: <6} :: {} {} {}", &status, &source, &arrow, &target);
-    };
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for row in &rows {
+        let key = status_key(&row.status);
+        match counts.iter_mut().find(|(status, _)| *status == key) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((key, 1)),
+        }
+    }
 
-    link_tasks.iter().for_each(|link_task| {
-        let source = &link_task.source.display().to_string();
-        let target = &link_task.target.display().to_string();
+    rows.retain(|row| {
+        (report.only.is_empty() || report.only.iter().any(|status| status.is_of(&row.status)))
+            && (!report.quiet || row.problem)
+    });
+
+    let group = |row: &Row| report.group_by.map(|grouping| group_key(row.task, grouping, &report.root));
+    if report.group_by.is_some() {
+        rows.sort_by_cached_key(group);
+    }
+
+    let mut groups: Vec<(Option<String>, Vec<&Row>)> = Vec::new();
+    for row in &rows {
+        let row_group = group(row);
+        match groups.last_mut() {
+            Some((last_group, group_rows)) if *last_group == row_group => group_rows.push(row),
+            _ => groups.push((row_group, vec![row])),
+        }
+    }
 
-        match &link_task.target_state {
-            TargetState::Absent => {
-                print_status(&to_link, &source, &target);
+    for (group, rows) in groups {
+        if let Some(group) = group {
+            writeln!(out, "{}", format!("[{}]", group).bold())?;
+        }
+
+        if report.tree {
+            present_tree(out, &rows, report)?;
+            continue;
+        }
+
+        for row in rows {
+            writeln!(out, 
+                "{: <width$} :: {} {} {}",
+                row.status,
+                row.task.source.display(),
+                arrow,
+                row.task.target.display(),
+                width = width
+            )?;
+            for detail in &row.details {
+                writeln!(out, "\t{}", detail)?;
             }
-            TargetState::AlienNode(_metadata) => {
-                print_status(&alien_file, &source, &target);
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Directory of the targets tree, targets may have children as well
+#[derive(Default)]
+struct TreeNode<'a> {
+    children: BTreeMap<String, TreeNode<'a>>,
+    row: Option<&'a Row<'a>>,
+}
+
+/// Prints targets as a tree of directories, `~` stands for the home
+///
+/// Sources are shown relative to the linkfile directory, lines longer than
+/// the terminal are shortened in the middle.
+fn present_tree(out: &mut dyn Write, rows: &[&Row], report: &Report) -> io::Result<()> {
+    let mut roots: BTreeMap<String, TreeNode> = BTreeMap::new();
+
+    for row in rows {
+        let target = &row.task.target;
+        let (root, relative) = match report.home.as_deref().map(|home| target.strip_prefix(home)) {
+            Some(Ok(relative)) => ("~".to_string(), relative),
+            _ => match target.components().next() {
+                Some(first) => (
+                    Path::new(first.as_os_str()).display().to_string(),
+                    target.strip_prefix(first).unwrap_or(target),
+                ),
+                None => continue,
+            },
+        };
+
+        let node = relative
+            .iter()
+            .fold(roots.entry(root).or_default(), |node, name| {
+                node.children.entry(name.to_string_lossy().to_string()).or_default()
+            });
+        node.row = Some(row);
+    }
+
+    for (name, node) in &roots {
+        match node.row {
+            Some(row) => present_tree_row(out, row, name, "", "", report)?,
+            None => writeln!(out, "{}", name)?,
+        }
+        present_tree_children(out, node, "", report)?;
+    }
+
+    Ok(())
+}
+
+fn present_tree_children(out: &mut dyn Write, node: &TreeNode, prefix: &str, report: &Report) -> io::Result<()> {
+    let count = node.children.len();
+
+    for (index, (name, child)) in node.children.iter().enumerate() {
+        let is_last = index + 1 == count;
+        let connector = if is_last { "└── " } else { "├── " };
+        let indent = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+
+        // Chains of directories without targets are shown as one path
+        let mut name = name.clone();
+        let mut child = child;
+        while child.row.is_none() && child.children.len() == 1 {
+            let (next_name, next_child) = child.children.iter().next().expect("Single child");
+            name = format!("{}/{}", name, next_name);
+            child = next_child;
+        }
+
+        match child.row {
+            Some(row) => present_tree_row(out, row, &name, &format!("{}{}", prefix, connector), &indent, report)?,
+            None => writeln!(out, "{}{}{}", prefix, connector, name)?,
+        }
+        present_tree_children(out, child, &indent, report)?;
+    }
+
+    Ok(())
+}
+
+fn present_tree_row(
+    out: &mut dyn Write,
+    row: &Row,
+    name: &str,
+    lead: &str,
+    indent: &str,
+    report: &Report,
+) -> io::Result<()> {
+    let source = row.task.source.strip_prefix(&report.root).unwrap_or(&row.task.source);
+    let mut source = source.display().to_string();
+    let mut name = name.to_string();
+
+    if let Some(width) = report.width {
+        // Status is colored, only its text takes space
+        let fixed = lead.chars().count() + 2 + row.status.chars().count() + 4;
+        let available = width.saturating_sub(fixed);
+        let length = name.chars().count() + source.chars().count();
+        if length > available {
+            let excess = length - available;
+            let source_length = source.chars().count();
+            source = shorten(&source, source_length.saturating_sub(excess).max(MIN_SHORTENED));
+            let name_length = available.saturating_sub(source.chars().count());
+            name = shorten(&name, name_length.max(MIN_SHORTENED));
+        }
+    }
+
+    writeln!(out, "{}{}  {} {} {}", lead, name, row.status, "<-".magenta().bold(), source)?;
+    for detail in &row.details {
+        writeln!(out, "{}  {}", indent, detail)?;
+    }
+
+    Ok(())
+}
+
+const MIN_SHORTENED: usize = 8;
+
+/// Replaces the middle of the text with `…` to fit the length
+fn shorten(text: &str, length: usize) -> String {
+    let count = text.chars().count();
+    if count <= length {
+        return text.to_string();
+    }
+
+    let kept = length.saturating_sub(1);
+    let head = kept / 2;
+    let tail = kept - head;
+    let start: String = text.chars().take(head).collect();
+    let end: String = text.chars().skip(count - tail).collect();
+    format!("{}…{}", start, end)
+}
+
+fn present_summary(out: &mut dyn Write, counts: &[(String, usize)]) -> io::Result<()> {
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        return Ok(());
+    }
+
+    let parts: Vec<String> = counts
+        .iter()
+        .map(|(status, count)| format!("{} {}", count, status.replace('_', " ")))
+        .collect();
+    let links = if total == 1 { "link" } else { "links" };
+    writeln!(out, "{} {} {}: {}", "Summary:".bold(), total, links, parts.join(", "))
+}
+
+pub fn present_result(out: &mut dyn Write, linkage_result: &LinkageResult, report: &Report) -> io::Result<()> {
+    match linkage_result {
+        LinkageResult::DryResult(planned_links, hooks) => {
+            present_dry_result(out, planned_links, hooks, report)
+        }
+        LinkageResult::PreconditionFailed(reason) => {
+            writeln!(out, "Precondition failed: {}. Try to run in the dry mode.", reason)?;
+            present_precondition(out, reason, report)
+        }
+        LinkageResult::HookFailed(hook_run) => {
+            writeln!(out, "Hook failed, nothing was linked:")?;
+            present_hook_run(out, hook_run)
+        }
+        LinkageResult::Completed(link_states, hook_runs) => {
+            let counts = present_completed(out, link_states, report)?;
+            let hook_runs: Vec<&HookRun> = hook_runs
+                .iter()
+                .filter(|hook_run| !report.quiet || hook_run.is_failure())
+                .collect();
+            if !hook_runs.is_empty() {
+                writeln!(out, "Linkfile hooks:")?;
+                for hook_run in hook_runs {
+                    present_hook_run(out, hook_run)?;
+                }
             }
-            TargetState::AlienLink(_metadata) => {
-                print_status(&alien_link, &source, &target);
+            present_summary(out, &counts)
+        }
+    }
+}
+
+fn target_state_status(target_state: &TargetState) -> ColoredString {
+    match target_state {
+        TargetState::Absent => "TODO".yellow().bold(),
+        TargetState::AlienNode => "ALIEN".red().bold(),
+        TargetState::AlienLink => "ALIEN".red().bold(),
+        TargetState::DanglingLink => "DANGLING".red().bold(),
+        TargetState::Linked => "LINKED".green().bold(),
+        TargetState::LinkedIndirect => "INDIRECT".green().bold(),
+        TargetState::Outdated => "OUTDATED".yellow().bold(),
+        TargetState::Modified => "MODIFIED".red().bold(),
+        TargetState::SourceMissing => "MISSING".red().bold(),
+    }
+}
+
+fn is_target_state_problem(target_state: &TargetState) -> bool {
+    matches!(
+        target_state,
+        TargetState::AlienNode
+            | TargetState::AlienLink
+            | TargetState::DanglingLink
+            | TargetState::Modified
+            | TargetState::SourceMissing
+    )
+}
+
+fn task_row(link_task: &LinkTask) -> Row<'_> {
+    Row {
+        status: target_state_status(&link_task.target_state),
+        task: link_task,
+        problem: is_target_state_problem(&link_task.target_state),
+        details: Vec::new(),
+    }
+}
+
+/// Shows examined targets with their states
+pub fn present_link_tasks(out: &mut dyn Write, link_tasks: &[LinkTask], report: &Report) -> io::Result<()> {
+    let rows = link_tasks.iter().map(task_row).collect();
+    let counts = present_rows(out, rows, 8, report)?;
+    present_summary(out, &counts)
+}
+
+/// Warns about uncommitted changes of the sources
+pub fn present_changes(out: &mut dyn Write, changes: &[Change]) -> io::Result<()> {
+    let dirty = "DIRTY".yellow().bold();
+
+    if !changes.is_empty() {
+        writeln!(out, "Sources have uncommitted changes:")?;
+    }
+    for change in changes {
+        writeln!(out, "{: <8} :: {}", dirty, change.path.display())?;
+    }
+
+    Ok(())
+}
+
+/// Shows matches of the wildcard sources with the rules that left them out
+pub fn present_ignored(out: &mut dyn Write, ignored: &[Ignored]) -> io::Result<()> {
+    let status = "IGNORED".blue().bold();
+
+    for ignored in ignored {
+        writeln!(out, "{: <8} :: {}", status, ignored.source.display())?;
+        writeln!(out, "\t{}", ignored.rule)?;
+    }
+
+    Ok(())
+}
+
+/// Shows the targets that refused the linkage
+fn present_precondition(out: &mut dyn Write, reason: &Precondition, report: &Report) -> io::Result<()> {
+    let Precondition::TargetsExist(link_tasks) = reason;
+    let rows = link_tasks
+        .iter()
+        .map(|link_task| {
+            let mut row = task_row(link_task);
+            row.problem = true;
+            row.details.push(match link_task.on_conflict {
+                Some(policy) => format!("{} {}", "on conflict:".cyan(), policy),
+                None => "strict mode requires the target to be absent".to_string(),
+            });
+            row
+        })
+        .collect();
+    present_rows(out, rows, 8, report).map(|_| ())
+}
+
+fn present_dry_result(
+    out: &mut dyn Write,
+    planned_links: &[PlannedLink],
+    hooks: &Hooks,
+    report: &Report,
+) -> io::Result<()> {
+    let rows = planned_links
+        .iter()
+        .map(|planned_link| {
+            let link_task = &planned_link.task;
+            let mut row = task_row(link_task);
+
+            if let TargetState::Absent = link_task.target_state {
+                if planned_link.actions.is_empty() {
+                    row.problem = true;
+                    row.details.push("parent directory is missing and may not be created".to_string());
+                }
+                planned_link.actions.iter().for_each(|action| match action {
+                    Action::CreateParent { path } => {
+                        row.details.push(format!("{} directory {}", "would create".cyan(), path.display()))
+                    }
+                    Action::Decrypt { encryption, .. } => {
+                        row.details.push(format!("{} {} source", "would decrypt".cyan(), encryption))
+                    }
+                    _ => {}
+                });
+                row.details.extend(planned_hook_lines(&link_task.hooks));
             }
-            TargetState::Linked(_metadata) => {
-                print_status(&linked, &source, &target);
+            if let Some(policy) = link_task.on_conflict.filter(|_| link_task.is_conflict()) {
+                let overridden = policy.source.overridden();
+                row.details.push(format!("{} {}, overrides {}", "on conflict:".cyan(), policy, overridden));
             }
+            row
+        })
+        .collect();
+    let counts = present_rows(out, rows, 8, report)?;
+
+    let is_linking_planned = planned_links
+        .iter()
+        .any(|planned_link| matches!(planned_link.task.target_state, TargetState::Absent));
+    let has_hooks = hooks.pre_link.is_some() || hooks.post_link.is_some();
+    if is_linking_planned && has_hooks && !report.quiet {
+        writeln!(out, "Linkfile hooks:")?;
+        for line in planned_hook_lines(hooks) {
+            writeln!(out, "\t{}", line)?;
         }
-    })
+    }
+    present_summary(out, &counts)
 }
 
-fn present_completed(link_states: &Vec<LinkState>) {
+pub fn present_plan(out: &mut dyn Write, plan: &Plan, report: &Report) -> io::Result<()> {
     let arrow = "->".magenta().bold();
+
+    let rows = plan
+        .links
+        .iter()
+        .map(|planned_link| {
+            let link_task = &planned_link.task;
+            let mut row = task_row(link_task);
+            row.problem |= link_task.target_state == TargetState::Absent && planned_link.actions.is_empty();
+
+            let mode = plan.strictness.mode_of(link_task, plan.mode);
+            let policy = link_task.conflict_policy(mode, plan.explicit_mode);
+            if let Some(policy) = policy.filter(|_| link_task.is_conflict()) {
+                row.details.push(format!("on conflict: {}", policy));
+            }
+            row.details.extend(planned_link.actions.iter().map(|action| match action {
+                Action::CreateParent { path } => format!("create directory {}", path.display()),
+                Action::Backup { path } => format!("back up {}", path.display()),
+                Action::BackupSource { path } => format!("back up {} into the backup directory", path.display()),
+                Action::Remove { path } => format!("remove {}", path.display()),
+                Action::Delete { path } => format!("delete {}", path.display()),
+                Action::Adopt { source, target } => {
+                    format!("adopt {} as {}", target.display(), source.display())
+                }
+                Action::CreateSymlink { source, target } => {
+                    format!("link {} {} {}", target.display(), arrow, source.display())
+                }
+                Action::Decrypt { source, target, encryption } => {
+                    format!("decrypt {} {} {} ({})", source.display(), arrow, target.display(), encryption)
+                }
+                Action::SetAttributes { path, attributes } => {
+                    format!("set {} on {}", attributes_status(attributes), path.display())
+                }
+            }));
+
+            if !planned_link.actions.is_empty() {
+                row.details.extend(planned_hook_lines(&link_task.hooks));
+            }
+            row
+        })
+        .collect();
+
+    let counts = present_rows(out, rows, 8, report)?;
+    present_summary(out, &counts)
+}
+
+fn attributes_status(attributes: &Attributes) -> String {
+    let mut parts = Vec::new();
+    if let Some(uid) = attributes.uid {
+        parts.push(format!("owner {}", uid));
+    }
+    if let Some(gid) = attributes.gid {
+        parts.push(format!("group {}", gid));
+    }
+    if let Some(mode) = attributes.mode {
+        parts.push(format!("mode {:04o}", mode));
+    }
+    parts.join(", ")
+}
+
+fn planned_hook_lines(hooks: &Hooks) -> Vec<String> {
+    [HookKind::PreLink, HookKind::PostLink]
+        .into_iter()
+        .filter_map(|kind| hooks.command(kind).map(|command| (kind, command)))
+        .map(|(kind, command)| format!("{} {}: {}", "would run".cyan(), kind, command))
+        .collect()
+}
+
+fn hook_run_lines(hook_run: &HookRun) -> Vec<String> {
+    let status = match &hook_run.status {
+        HookStatus::Planned => "would run".cyan(),
+        HookStatus::Succeeded => "OK".green().bold(),
+        HookStatus::Failed(_) | HookStatus::IoError(_) => "FAILED".red().bold(),
+    };
+
+    let mut lines = vec![format!("{} {}: {}", status, hook_run.kind, hook_run.command)];
+
+    match &hook_run.status {
+        HookStatus::Planned | HookStatus::Succeeded => {}
+        HookStatus::Failed(exit_status) => lines.push(format!("\t{}", exit_status)),
+        HookStatus::IoError(e) => lines.push(format!("\t{}", e)),
+    }
+    lines
+}
+
+fn present_hook_run(out: &mut dyn Write, hook_run: &HookRun) -> io::Result<()> {
+    for line in hook_run_lines(hook_run) {
+        writeln!(out, "\t{}", line)?;
+    }
+
+    Ok(())
+}
+
+fn present_completed(
+    out: &mut dyn Write,
+    link_states: &[LinkState],
+    report: &Report,
+) -> io::Result<Vec<(String, usize)>> {
+    let rows = link_states
+        .iter()
+        .map(|link_state| {
+            let (status, problem) = match &link_state.result {
+                LinkResult::Existed => ("EXISTED".blue().bold(), false),
+                LinkResult::Skipped => ("SKIPPED".yellow().bold(), true),
+                LinkResult::Success => ("SUCCESS".green().bold(), false),
+                LinkResult::Normalized => ("NORMALIZED".green().bold(), false),
+                LinkResult::SourceMissing => ("MISSING".red().bold(), true),
+                LinkResult::ParentMissing => ("NO PARENT".red().bold(), true),
+                LinkResult::StateChanged(_) => ("CHANGED".red().bold(), true),
+                LinkResult::IoError(_) => ("ERROR".red().bold(), true),
+            };
+
+            let mut details = Vec::new();
+            match &link_state.result {
+                LinkResult::ParentMissing => {
+                    details.push("parent directory is missing and may not be created".to_string())
+                }
+                LinkResult::StateChanged(target_state) => details.push(format!(
+                    "target is {} now, the plan was made for {}",
+                    target_state, link_state.task.target_state
+                )),
+                LinkResult::IoError(e) => details.push(e.to_string()),
+                _ => {}
+            }
+            details.extend(link_state.hooks.iter().flat_map(hook_run_lines));
+
+            Row {
+                status,
+                task: &link_state.task,
+                problem: problem || link_state.hooks.iter().any(HookRun::is_failure),
+                details,
+            }
+        })
+        .collect();
+
+    present_rows(out, rows, 10, report)
+}
+
+pub fn present_backups(out: &mut dyn Write, backup_states: &[BackupState]) -> io::Result<()> {
+    let arrow = "<-".magenta().bold();
+    let present = "BACKUP".blue().bold();
+    let to_restore = "RESTORE".yellow().bold();
+    let to_remove = "REMOVE".yellow().bold();
+    let restored = "RESTORED".green().bold();
+    let removed = "REMOVED".green().bold();
     let error = "ERROR".red().bold();
-    let success = "SUCCESS".green().bold();
-    let skipped = "SKIPPED".yellow().bold();
-    let existed = "EXISTED".blue().bold();
 
-    let print_status = |status, source: &str, target: &str| {
-        println!("{: <7} :: {} {} {}", &status, &source, &arrow, &target);
+    let print_status = |out: &mut dyn Write, status, target: &str, backup: &str| {
+        writeln!(out, "{: <8} :: {} {} {}", &status, &target, &arrow, &backup)
     };
 
-    link_states.iter().for_each(|link_state| {
-        let source = &link_state.task.source.display().to_string();
-        let target = &link_state.task.target.display().to_string();
+    for backup_state in backup_states {
+        let target = &backup_state.backup.target.display().to_string();
+        let backup = &backup_state.backup.path.display().to_string();
 
-        match &link_state.result {
-            LinkResult::Existed => {
-                print_status(&existed, &source, &target);
+        match &backup_state.result {
+            BackupResult::Present => print_status(out, &present, target, backup)?,
+            BackupResult::ToRestore => print_status(out, &to_restore, target, backup)?,
+            BackupResult::ToRemove => print_status(out, &to_remove, target, backup)?,
+            BackupResult::Restored => print_status(out, &restored, target, backup)?,
+            BackupResult::Removed => print_status(out, &removed, target, backup)?,
+            BackupResult::IoError(e) => {
+                print_status(out, &error, target, backup)?;
+                writeln!(out, "\t{}", e)?;
             }
-            LinkResult::Skipped => {
-                print_status(&skipped, &source, &target);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn present_drift(out: &mut dyn Write, drift_states: &[DriftState], show_merge: bool) -> io::Result<()> {
+    let arrow = "->".magenta().bold();
+
+    for drift_state in drift_states {
+        let status = match &drift_state.status {
+            DriftStatus::Unchanged => "UNCHANGED".green().bold(),
+            DriftStatus::LocallyModified => "LOCAL".yellow().bold(),
+            DriftStatus::UpstreamChanged => "UPSTREAM".yellow().bold(),
+            DriftStatus::Diverged(None | Some(Merge::Clean(_))) => "DIVERGED".yellow().bold(),
+            DriftStatus::Diverged(Some(Merge::Conflict(_))) => "CONFLICT".red().bold(),
+            DriftStatus::Untracked => "UNTRACKED".blue().bold(),
+            DriftStatus::Missing => "MISSING".red().bold(),
+            DriftStatus::IoError(_) => "ERROR".red().bold(),
+        };
+        writeln!(
+            out,
+            "{: <9} :: {} {} {}",
+            status,
+            drift_state.task.source.display(),
+            arrow,
+            drift_state.task.target.display()
+        )?;
+
+        match &drift_state.status {
+            DriftStatus::Diverged(Some(Merge::Clean(merged) | Merge::Conflict(merged))) if show_merge => {
+                out.write_all(merged)?;
             }
-            LinkResult::Success => {
-                print_status(&success, &source, &target);
+            DriftStatus::Diverged(None) if show_merge => {
+                writeln!(out, "\tdeployed content is not kept, set `merge_base` in [meta] to merge")?
             }
-            LinkResult::IoError(e) => {
-                print_status(&error, &source, &target);
-                println!("\t{}", e);
+            DriftStatus::IoError(e) => writeln!(out, "\t{}", e)?,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub fn present_prune(
+    out: &mut dyn Write,
+    prune_states: &[PruneState],
+    directory_states: &[DirectoryState],
+    hook_runs: &[HookRun],
+) -> io::Result<()> {
+    let arrow = "->".magenta().bold();
+    let stale = "STALE".yellow().bold();
+    let removed = "REMOVED".green().bold();
+    let error = "ERROR".red().bold();
+
+    let print_status = |out: &mut dyn Write, status, target: &str, destination: &str| {
+        writeln!(out, "{: <7} :: {} {} {}", &status, &target, &arrow, &destination)
+    };
+
+    for prune_state in prune_states {
+        let target = &prune_state.link.target.display().to_string();
+        let destination = &prune_state.link.destination.display().to_string();
+
+        match &prune_state.result {
+            PruneResult::Stale => print_status(out, &stale, target, destination)?,
+            PruneResult::Removed => print_status(out, &removed, target, destination)?,
+            PruneResult::IoError(e) => {
+                print_status(out, &error, target, destination)?;
+                writeln!(out, "\t{}", e)?;
             }
         }
-    })
+
+        for hook_run in &prune_state.hooks {
+            present_hook_run(out, hook_run)?;
+        }
+    }
+
+    for directory_state in directory_states {
+        let status = match &directory_state.result {
+            PruneResult::Stale => &stale,
+            PruneResult::Removed => &removed,
+            PruneResult::IoError(_) => &error,
+        };
+        writeln!(out, "{: <7} :: {}", status, directory_state.path.display())?;
+        if let PruneResult::IoError(e) = &directory_state.result {
+            writeln!(out, "\t{}", e)?;
+        }
+    }
+
+    if !hook_runs.is_empty() {
+        writeln!(out, "Linkfile hooks:")?;
+        for hook_run in hook_runs {
+            present_hook_run(out, hook_run)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linker::{ConflictPolicy, Mode, ParentPolicy, PolicySource, Strictness, TargetRoot};
+    use crate::linkfile::OnConflict;
+
+    fn task(source: &str, target: &str, target_state: TargetState, tag: Option<&str>) -> LinkTask {
+        LinkTask {
+            source: PathBuf::from("/dotfiles").join(source),
+            target: PathBuf::from(target),
+            target_state,
+            tag: tag.map(str::to_string),
+            hooks: Hooks::default(),
+            attributes: Attributes::default(),
+            parents: ParentPolicy::default(),
+            encryption: None,
+            on_conflict: None,
+            deployed: None,
+        }
+    }
+
+    fn tasks() -> Vec<LinkTask> {
+        vec![
+            task("fish", "/home/link/.config/fish", TargetState::Linked, Some("shell")),
+            task("nvim", "/home/link/.config/nvim", TargetState::Absent, Some("editor")),
+            task("vimrc", "/home/link/.vimrc", TargetState::AlienNode, Some("editor")),
+            task("git/config", "/etc/gitconfig", TargetState::SourceMissing, None),
+        ]
+    }
+
+    fn report() -> Report {
+        Report {
+            root: PathBuf::from("/dotfiles"),
+            home: Some(PathBuf::from("/home/link")),
+            ..Report::default()
+        }
+    }
+
+    /// Output of the presenter without colors
+    fn capture(present: impl FnOnce(&mut dyn Write) -> io::Result<()>) -> String {
+        set_colors(false);
+        let mut out = Vec::new();
+        present(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn link_tasks() {
+        insta::assert_snapshot!(capture(|out| present_link_tasks(out, &tasks(), &report())));
+    }
+
+    #[test]
+    fn filtered_and_grouped_link_tasks() {
+        let quiet = Report { quiet: true, ..report() };
+        insta::assert_snapshot!("quiet_link_tasks", capture(|out| present_link_tasks(out, &tasks(), &quiet)));
+
+        let grouped = Report {
+            only: vec![Status::Linked, Status::Todo],
+            group_by: Some(Grouping::Tag),
+            ..report()
+        };
+        insta::assert_snapshot!("grouped_link_tasks", capture(|out| present_link_tasks(out, &tasks(), &grouped)));
+    }
+
+    #[test]
+    fn link_tasks_tree() {
+        let tree = Report { tree: true, width: Some(40), ..report() };
+        insta::assert_snapshot!(capture(|out| present_link_tasks(out, &tasks(), &tree)));
+    }
+
+    #[test]
+    fn plan() {
+        let nvim = task("nvim", "/home/link/.config/nvim", TargetState::Absent, None);
+        let vimrc = task("vimrc", "/home/link/.vimrc", TargetState::AlienNode, None);
+        let plan = Plan {
+            linkfile: PathBuf::from("/dotfiles/dotfiles.toml"),
+            mode: Mode::Force,
+            explicit_mode: true,
+            target_root: TargetRoot::default(),
+            strictness: Strictness::default(),
+            links: vec![
+                PlannedLink {
+                    actions: vec![
+                        Action::CreateParent { path: PathBuf::from("/home/link/.config") },
+                        Action::CreateSymlink { source: nvim.source.clone(), target: nvim.target.clone() },
+                    ],
+                    task: nvim,
+                },
+                PlannedLink {
+                    actions: vec![
+                        Action::Backup { path: vimrc.target.clone() },
+                        Action::CreateSymlink { source: vimrc.source.clone(), target: vimrc.target.clone() },
+                    ],
+                    task: vimrc,
+                },
+            ],
+        };
+        insta::assert_snapshot!(capture(|out| present_plan(out, &plan, &report())));
+    }
+
+    #[test]
+    fn completed_result() {
+        let link_states = tasks()
+            .into_iter()
+            .zip([
+                LinkResult::Existed,
+                LinkResult::Success,
+                LinkResult::Skipped,
+                LinkResult::IoError(io::Error::new(io::ErrorKind::NotFound, "source is missing")),
+            ])
+            .map(|(task, result)| LinkState { task, result, actions: Vec::new(), hooks: Vec::new() })
+            .collect();
+        let result = LinkageResult::Completed(link_states, Vec::new());
+        insta::assert_snapshot!(capture(|out| present_result(out, &result, &report())));
+
+        let only = Report { only: vec![Status::Skipped, Status::Error], ..report() };
+        insta::assert_snapshot!("only_completed_result", capture(|out| present_result(out, &result, &only)));
+    }
+
+    #[test]
+    fn precondition_failed_result() {
+        let mut vimrc = task("vimrc", "/home/link/.vimrc", TargetState::AlienNode, None);
+        vimrc.on_conflict = Some(ConflictPolicy { on_conflict: OnConflict::Fail, source: PolicySource::Meta });
+        let fish = task("fish", "/home/link/.config/fish", TargetState::Linked, None);
+        let result = LinkageResult::PreconditionFailed(Precondition::TargetsExist(vec![vimrc, fish]));
+        insta::assert_snapshot!(capture(|out| present_result(out, &result, &report())));
+    }
+
+    #[test]
+    fn statuses_are_matched_by_key() {
+        assert!(Status::NoParent.is_of("NO PARENT"));
+        assert!(Status::Todo.is_of("TODO"));
+        assert!(!Status::Alien.is_of("DANGLING"));
+    }
+
+    #[test]
+    fn colors_follow_choice_and_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| vars.iter().find(|(var, _)| *var == name).map(|(_, value)| value.to_string())
+        };
+
+        assert!(color_enabled(ColorChoice::Auto, env(&[]), true));
+        assert!(!color_enabled(ColorChoice::Auto, env(&[]), false));
+        assert!(!color_enabled(ColorChoice::Auto, env(&[("NO_COLOR", "1")]), true));
+        assert!(color_enabled(ColorChoice::Auto, env(&[("NO_COLOR", "")]), true));
+        assert!(color_enabled(ColorChoice::Auto, env(&[("CLICOLOR_FORCE", "1"), ("NO_COLOR", "1")]), false));
+        assert!(!color_enabled(ColorChoice::Auto, env(&[("CLICOLOR_FORCE", "0")]), false));
+        assert!(color_enabled(ColorChoice::Always, env(&[("NO_COLOR", "1")]), false));
+        assert!(!color_enabled(ColorChoice::Never, env(&[("CLICOLOR_FORCE", "1")]), true));
+    }
+
+    #[test]
+    fn shorten_keeps_both_ends() {
+        assert_eq!(shorten("nvim/init.lua", 20), "nvim/init.lua");
+        assert_eq!(shorten("config/nvim/lua/plugins.lua", 12), "confi…ns.lua");
+        assert_eq!(shorten("config/nvim/lua/plugins.lua", 12).chars().count(), 12);
+    }
 }
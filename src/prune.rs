@@ -0,0 +1,557 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::{normalize, FileSystem, NodeType};
+use crate::hooks::{HookKind, HookRun, HookRunner};
+use crate::ignore;
+use crate::linker::{LinkTask, Mode, TargetRoot};
+use crate::linkfile::{Destination, Dotfiles, Hooks, Link, Target};
+use crate::state::State;
+
+/// Symlink into the linkfile directory that is not produced by any link
+#[derive(Debug, Clone)]
+pub struct StaleLink {
+    pub target: PathBuf,
+    pub destination: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum PruneResult {
+    Stale,
+    Removed,
+    IoError(io::Error),
+}
+
+#[derive(Debug)]
+pub struct PruneState {
+    pub link: StaleLink,
+    pub result: PruneResult,
+    pub hooks: Vec<HookRun>,
+}
+
+/// Directory created by rinku for the links that became empty
+#[derive(Debug)]
+pub struct DirectoryState {
+    pub path: PathBuf,
+    pub result: PruneResult,
+}
+
+/// Finds links to prune
+///
+/// A link is stale when no entry of the linkfile produces it anymore, tags
+/// and platforms do not matter, links of the disabled entries stay. Links
+/// recorded in the state are stale as soon as they point into the linkfile
+/// directory elsewhere than the linkfile says. Other symlinks found next to
+/// the configured targets are only considered stale when they dangle.
+pub fn find_stale_links(
+    fs: &dyn FileSystem,
+    dotfiles: &Dotfiles,
+    target_root: &TargetRoot,
+    state: &State,
+) -> io::Result<Vec<StaleLink>> {
+    let root = &dotfiles.root;
+    let configured = configured_links(fs, dotfiles, target_root);
+    let is_configured = |target: &Path, destination: &Path| {
+        configured.contains(&(destination.to_path_buf(), target.to_path_buf()))
+    };
+
+    let mut stale_links = Vec::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+
+    for record in &state.links {
+        if !visited.insert(record.target.clone()) {
+            continue;
+        }
+        if let Some(destination) = read_link_destination(fs, &record.target)? {
+            if destination.starts_with(root) && !is_configured(&record.target, &destination) {
+                stale_links.push(StaleLink { target: record.target.clone(), destination });
+            }
+        }
+    }
+
+    let directories: HashSet<&Path> = configured
+        .iter()
+        .map(|(_, target)| target.as_path())
+        .chain(state.links.iter().map(|record| record.target.as_path()))
+        .filter_map(Path::parent)
+        .collect();
+
+    for directory in directories {
+        let entries = match fs.read_dir(directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for target in entries {
+            if visited.contains(&target) {
+                continue;
+            }
+            if let Some(destination) = read_link_destination(fs, &target)? {
+                let is_dangling = fs.symlink_node_type(&destination).is_err();
+                if destination.starts_with(root) && is_dangling && !is_configured(&target, &destination) {
+                    visited.insert(target.clone());
+                    stale_links.push(StaleLink { target, destination });
+                }
+            }
+        }
+    }
+
+    stale_links.sort_by(|l, r| l.target.cmp(&r.target));
+    Ok(stale_links)
+}
+
+/// Forgets copies no entry of the linkfile deploys anymore
+///
+/// Copies themselves are left in place as they may have been edited, only
+/// their records go, the content kept for them is removed by
+/// `BaseStore::collect_garbage`.
+pub fn forget_stale_copies(fs: &dyn FileSystem, dotfiles: &Dotfiles, target_root: &TargetRoot, state: &mut State) {
+    let configured = configured_links(fs, dotfiles, target_root);
+    state
+        .copies
+        .retain(|copy| configured.contains(&(normalize(&copy.source), normalize(&copy.target))));
+}
+
+/// `(source, target)` pairs of every link of the linkfile regardless of
+/// its tag and platform
+fn configured_links(
+    fs: &dyn FileSystem,
+    dotfiles: &Dotfiles,
+    target_root: &TargetRoot,
+) -> HashSet<(PathBuf, PathBuf)> {
+    let meta = &dotfiles.linkfile.meta;
+    let mut configured = HashSet::new();
+
+    for link in &dotfiles.linkfile.links {
+        // Sources with the path they add to the target, one per wildcard match
+        let sources: Vec<(PathBuf, Option<PathBuf>)> = if ignore::is_glob(&link.source) {
+            match ignore::expand(fs, &dotfiles.root, &link.source, &link.exclude, &meta.ignore) {
+                Ok(expansion) => expansion
+                    .matches
+                    .into_iter()
+                    .map(|source| {
+                        let suffix = source.strip_prefix(&expansion.base).map(Path::to_path_buf).ok();
+                        (source, suffix)
+                    })
+                    .collect(),
+                Err(_) => vec![],
+            }
+        } else {
+            vec![(dotfiles.root.join(&link.source), None)]
+        };
+
+        for target in link_targets(link) {
+            let target = target_root.resolve(Path::new(target));
+            for (source, suffix) in &sources {
+                let target = match suffix {
+                    Some(suffix) => target.join(suffix),
+                    None => target.clone(),
+                };
+                configured.insert((normalize(source), normalize(&target)));
+            }
+        }
+    }
+
+    configured
+}
+
+/// Targets of the link on every platform
+fn link_targets(link: &Link) -> Vec<&str> {
+    let destinations: Vec<&Destination> = match &link.target {
+        Target::Unified(destination) => vec![destination],
+        Target::Platform(platforms) => platforms.values().collect(),
+    };
+
+    destinations
+        .into_iter()
+        .flat_map(|destination| match destination {
+            Destination::Single(target) => vec![target.as_str()],
+            Destination::Multi(targets) => targets.iter().map(String::as_str).collect(),
+        })
+        .collect()
+}
+
+/// Removes stale links unless the mode is dry
+///
+/// `post_unlink` hooks are taken from the link that still has the same
+/// source, e.g. when only its target was changed. The dry mode reports the
+/// hooks it would run.
+pub fn prune_links(
+    fs: &dyn FileSystem,
+    mode: Mode,
+    stale_links: Vec<StaleLink>,
+    link_tasks: &[LinkTask],
+    hook_runner: &HookRunner,
+    state: &mut State,
+) -> (Vec<PruneState>, Vec<HookRun>) {
+    let no_hooks = Hooks::default();
+
+    let prune_states: Vec<PruneState> = stale_links
+        .into_iter()
+        .map(|link| {
+            let result = match mode {
+                Mode::Dry => PruneResult::Stale,
+                _ => match fs.remove_link(&link.target) {
+                    Ok(()) => {
+                        state.forget_link(&link.target);
+                        PruneResult::Removed
+                    }
+                    Err(err) => {
+                        return PruneState { link, result: PruneResult::IoError(err), hooks: vec![] };
+                    }
+                },
+            };
+
+            let hooks = link_tasks
+                .iter()
+                .find(|task| task.source == link.destination)
+                .map_or(&no_hooks, |task| &task.hooks);
+            let hooks = hook_runner
+                .run_for_link(hooks, HookKind::PostUnlink, &link.destination, &link.target)
+                .into_iter()
+                .collect();
+
+            PruneState { link, result, hooks }
+        })
+        .collect();
+
+    let mut hook_runs = Vec::new();
+    let is_pruned = |state: &PruneState| matches!(state.result, PruneResult::Stale | PruneResult::Removed);
+    if prune_states.iter().any(is_pruned) {
+        hook_runs.extend(hook_runner.run_for_linkage(HookKind::PostUnlink));
+    }
+
+    (prune_states, hook_runs)
+}
+
+/// Removes empty directories recorded in the state that lead to the links
+/// pruned in this run, nested ones go first
+///
+/// Directories removed by someone else are forgotten, the ones that still
+/// have content are kept. The dry mode counts the pruned links as gone.
+pub fn prune_directories(
+    fs: &dyn FileSystem,
+    mode: Mode,
+    prune_states: &[PruneState],
+    state: &mut State,
+) -> Vec<DirectoryState> {
+    let pruned: Vec<&Path> = prune_states
+        .iter()
+        .filter(|prune_state| matches!(prune_state.result, PruneResult::Stale | PruneResult::Removed))
+        .map(|prune_state| prune_state.link.target.as_path())
+        .collect();
+    let mut directories = state.directories.clone();
+    directories.sort_by_key(|directory| Reverse(directory.components().count()));
+
+    let mut directory_states: Vec<DirectoryState> = Vec::new();
+    for path in directories {
+        let is_pruned_parent = pruned.iter().any(|target| target.starts_with(&path));
+        let entries = match fs.read_dir(&path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                state.forget_directory(&path);
+                continue;
+            }
+            Err(_) if !is_pruned_parent => continue,
+            Err(err) => {
+                directory_states.push(DirectoryState { path, result: PruneResult::IoError(err) });
+                continue;
+            }
+        };
+
+        let is_gone = |entry: &PathBuf| {
+            pruned.contains(&entry.as_path())
+                || directory_states.iter().any(|directory_state| {
+                    directory_state.path == *entry && !matches!(directory_state.result, PruneResult::IoError(_))
+                })
+        };
+        if !is_pruned_parent || !entries.iter().all(is_gone) {
+            continue;
+        }
+
+        let result = match mode {
+            Mode::Dry => PruneResult::Stale,
+            _ => match fs.remove_dir(&path) {
+                Ok(()) => {
+                    state.forget_directory(&path);
+                    PruneResult::Removed
+                }
+                Err(err) => PruneResult::IoError(err),
+            },
+        };
+        directory_states.push(DirectoryState { path, result });
+    }
+
+    directory_states
+}
+
+/// Destination of the symlink without `.` and `..`, `None` for other nodes
+fn read_link_destination(fs: &dyn FileSystem, path: &Path) -> io::Result<Option<PathBuf>> {
+    match fs.symlink_node_type(path) {
+        Ok(NodeType::Symlink) => {}
+        Ok(_) => return Ok(None),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    }
+
+    let destination = fs.read_link(path)?;
+    Ok(Some(normalize(&match path.parent() {
+        Some(parent) if destination.is_relative() => parent.join(destination),
+        _ => destination,
+    })))
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::filesystem::{MemoryFileSystem, MemoryNode};
+    use crate::hooks::HookStatus;
+    use crate::linkfile::Format;
+    use crate::state::{CopyRecord, LinkRecord};
+
+    fn dotfiles(content: &str) -> Dotfiles {
+        Dotfiles {
+            linkfile_path: PathBuf::from("/dotfiles/dotfiles.toml"),
+            root: PathBuf::from("/dotfiles"),
+            linkfile: toml::from_str(content).unwrap(),
+            format: Format::Toml,
+        }
+    }
+
+    /// State with the links that are present in the filesystem
+    fn linked(fs: &MemoryFileSystem, links: &[(&str, &str)]) -> State {
+        let mut state = State::default();
+        for (source, target) in links {
+            fs.add_symlink(target, source);
+            state.links.push(LinkRecord { source: PathBuf::from(source), target: PathBuf::from(target) });
+        }
+        state
+    }
+
+    /// Finds the stale links and removes them in the lazy mode
+    fn prune(fs: &MemoryFileSystem, dotfiles: &Dotfiles, state: &mut State) -> Vec<String> {
+        let stale_links = find_stale_links(fs, dotfiles, &TargetRoot::default(), state).unwrap();
+        let hooks = Hooks::default();
+        let hook_runner = HookRunner::new(&dotfiles.root, Mode::Lazy, &hooks);
+        let (prune_states, _) = prune_links(fs, Mode::Lazy, stale_links, &[], &hook_runner, state);
+        prune_states
+            .iter()
+            .map(|prune_state| {
+                assert!(matches!(prune_state.result, PruneResult::Removed), "{:?}", prune_state);
+                prune_state.link.target.display().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn links_of_disabled_entries_are_kept() {
+        let dotfiles = dotfiles(
+            r#"
+            [meta]
+            default_tags = []
+
+            [[link]]
+            source = "vimrc"
+            target = "/home/link/.vimrc"
+            tag = "work"
+
+            [[link]]
+            source = "profile.ps1"
+            target.windows = "/home/link/profile.ps1"
+
+            [[link]]
+            source = "config/*"
+            target = "/home/link/.config"
+            tag = "work"
+            "#,
+        );
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/vimrc");
+        fs.add_file("/dotfiles/config/fish");
+        let mut state = linked(
+            &fs,
+            &[
+                ("/dotfiles/vimrc", "/home/link/.vimrc"),
+                ("/dotfiles/profile.ps1", "/home/link/profile.ps1"),
+                ("../../dotfiles/config/fish", "/home/link/.config/fish"),
+            ],
+        );
+
+        assert_eq!(prune(&fs, &dotfiles, &mut state), Vec::<String>::new());
+        assert_eq!(fs.node("/home/link/.vimrc"), Some(MemoryNode::Symlink(PathBuf::from("/dotfiles/vimrc"))));
+        assert_eq!(state.links.len(), 3);
+    }
+
+    #[test]
+    fn links_to_moved_sources_are_stale() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vim/vimrc"
+            target = "/home/link/.vimrc"
+            "#,
+        );
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/vim/vimrc");
+        let mut state = linked(&fs, &[("/dotfiles/vimrc", "/home/link/.vimrc")]);
+        // Dangling link nobody recorded
+        fs.add_symlink("/home/link/.gvimrc", "/dotfiles/gvimrc");
+
+        assert_eq!(prune(&fs, &dotfiles, &mut state), vec!["/home/link/.gvimrc", "/home/link/.vimrc"]);
+        assert_eq!(fs.node("/home/link/.vimrc"), None);
+        assert!(state.links.is_empty());
+    }
+
+    #[test]
+    fn targets_changed_by_user_are_kept() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "vimrc"
+            target = "/home/link/.config/vimrc"
+            "#,
+        );
+        let fs = MemoryFileSystem::new();
+        fs.add_file("/dotfiles/vimrc");
+        fs.add_file("/dotfiles/nvim");
+        let mut state = linked(
+            &fs,
+            &[
+                ("/dotfiles/vimrc", "/home/link/.vimrc"),
+                ("/dotfiles/nvim", "/home/link/.nvimrc"),
+                ("/dotfiles/vimrc", "/home/link/.exrc"),
+            ],
+        );
+        // Links replaced by the user are not rinku's anymore
+        fs.remove_link(Path::new("/home/link/.nvimrc")).unwrap();
+        fs.add_file("/home/link/.nvimrc");
+        fs.remove_link(Path::new("/home/link/.exrc")).unwrap();
+        fs.add_symlink("/home/link/.exrc", "/etc/exrc");
+
+        // Only the link whose target was changed in the linkfile is stale
+        assert_eq!(prune(&fs, &dotfiles, &mut state), vec!["/home/link/.vimrc"]);
+        assert_eq!(fs.node("/home/link/.nvimrc"), Some(MemoryNode::File));
+        assert_eq!(fs.node("/home/link/.exrc"), Some(MemoryNode::Symlink(PathBuf::from("/etc/exrc"))));
+    }
+
+    #[test]
+    fn empty_parents_of_pruned_links_are_removed() {
+        let fs = MemoryFileSystem::new();
+        fs.add_dir("/home/link/.config/nvim/lua");
+        fs.add_symlink("/home/link/.config/nvim/lua/init.lua", "/dotfiles/init.lua");
+        fs.add_file("/home/link/.local/share/kept");
+        fs.add_dir("/home/link/.ssh");
+        let mut state = State {
+            directories: vec![
+                PathBuf::from("/home/link/.config"),
+                PathBuf::from("/home/link/.config/nvim"),
+                PathBuf::from("/home/link/.config/nvim/lua"),
+                PathBuf::from("/home/link/.local/share"),
+                // Parent of a configured link whose target is absent
+                PathBuf::from("/home/link/.ssh"),
+                PathBuf::from("/home/link/gone"),
+            ],
+            ..State::default()
+        };
+        let pruned = |result| {
+            let link = StaleLink {
+                target: PathBuf::from("/home/link/.config/nvim/lua/init.lua"),
+                destination: PathBuf::from("/dotfiles/init.lua"),
+            };
+            vec![PruneState { link, result, hooks: Vec::new() }]
+        };
+        let prune = |mode, state: &mut State, result| -> Vec<PathBuf> {
+            prune_directories(&fs, mode, &pruned(result), state)
+                .into_iter()
+                .map(|directory_state| directory_state.path)
+                .collect()
+        };
+        let parents = vec![
+            PathBuf::from("/home/link/.config/nvim/lua"),
+            PathBuf::from("/home/link/.config/nvim"),
+            PathBuf::from("/home/link/.config"),
+        ];
+
+        assert_eq!(prune(Mode::Dry, &mut state, PruneResult::Stale), parents);
+        assert!(fs.node("/home/link/.config/nvim/lua").is_some());
+
+        fs.remove_link(Path::new("/home/link/.config/nvim/lua/init.lua")).unwrap();
+        assert_eq!(prune(Mode::Lazy, &mut state, PruneResult::Removed), parents);
+        assert_eq!(fs.node("/home/link/.config"), None);
+        assert_eq!(fs.node("/home/link/.ssh"), Some(MemoryNode::Dir));
+        assert_eq!(
+            state.directories,
+            vec![PathBuf::from("/home/link/.local/share"), PathBuf::from("/home/link/.ssh")]
+        );
+    }
+
+    #[test]
+    fn copies_of_removed_entries_are_forgotten() {
+        let dotfiles = dotfiles(
+            r#"
+            [[link]]
+            source = "netrc.age"
+            target = "/home/link/.netrc"
+            encryption = "age"
+            tag = "work"
+        "#,
+        );
+        let fs = MemoryFileSystem::new();
+        let mut state = State::default();
+        for target in ["/home/link/.netrc", "/home/link/.pgpass"] {
+            fs.add_file_content(target, b"secret");
+            let source = PathBuf::from("/dotfiles/netrc.age");
+            state.copies.push(CopyRecord { source, target: PathBuf::from(target), hash: String::new() });
+        }
+
+        forget_stale_copies(&fs, &dotfiles, &TargetRoot::default(), &mut state);
+        let targets: Vec<_> = state.copies.iter().map(|copy| copy.target.clone()).collect();
+        assert_eq!(targets, vec![PathBuf::from("/home/link/.netrc")]);
+        assert!(fs.read(Path::new("/home/link/.pgpass")).is_ok());
+    }
+
+    #[test]
+    fn dry_prune_lists_hooks_it_would_run() {
+        let hooks = Hooks { post_unlink: Some("touch unlinked".to_string()), ..Hooks::default() };
+        let root = Path::new("/dotfiles");
+        let hook_runner = HookRunner::new(root, Mode::Dry, &hooks);
+        let stale_link = StaleLink {
+            target: PathBuf::from("/home/link/.vimrc"),
+            destination: PathBuf::from("/dotfiles/vimrc"),
+        };
+        let link_task = LinkTask {
+            source: PathBuf::from("/dotfiles/vimrc"),
+            target: PathBuf::from("/home/link/.config/vimrc"),
+            target_state: crate::linker::TargetState::Absent,
+            tag: None,
+            hooks: hooks.clone(),
+            attributes: Default::default(),
+            parents: Default::default(),
+            encryption: None,
+            on_conflict: None,
+            deployed: None,
+        };
+        let mut state = State::default();
+
+        let fs = MemoryFileSystem::new();
+        let (prune_states, hook_runs) =
+            prune_links(&fs, Mode::Dry, vec![stale_link], &[link_task], &hook_runner, &mut state);
+
+        assert!(matches!(prune_states[0].result, PruneResult::Stale));
+        let planned = |hook_runs: &[HookRun]| {
+            hook_runs
+                .iter()
+                .map(|hook_run| {
+                    let is_planned = matches!(hook_run.status, HookStatus::Planned);
+                    (hook_run.kind, hook_run.command.clone(), is_planned)
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = vec![(HookKind::PostUnlink, "touch unlinked".to_string(), true)];
+        assert_eq!(planned(&prune_states[0].hooks), expected);
+        assert_eq!(planned(&hook_runs), expected);
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::{io, process, thread};
+
+use crate::linkfile::{Encryption, Meta};
+
+/// Turns content of the encrypted source into the plaintext
+pub trait Decryptor {
+    fn decrypt(&self, encryption: Encryption, ciphertext: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Decrypts with the `age` or `gpg` executable found in `PATH`
+///
+/// The identity is passed to `age --identity`, for `gpg` it is the
+/// `--homedir` holding the keys.
+#[derive(Clone, Debug, Default)]
+pub struct CommandDecryptor {
+    identity: Option<PathBuf>,
+}
+
+impl CommandDecryptor {
+    /// Takes `[meta] identity` relative to the linkfile directory
+    pub fn new(root: &Path, meta: &Meta) -> Self {
+        CommandDecryptor {
+            identity: meta
+                .identity
+                .as_ref()
+                .map(|identity| root.join(crate::linker::expand_dest(Path::new(identity)))),
+        }
+    }
+
+    fn command(&self, encryption: Encryption) -> process::Command {
+        match encryption {
+            Encryption::Age => {
+                let mut command = process::Command::new("age");
+                command.arg("--decrypt");
+                if let Some(identity) = &self.identity {
+                    command.arg("--identity").arg(identity);
+                }
+                command
+            }
+            Encryption::Gpg => {
+                let mut command = process::Command::new("gpg");
+                command.args(["--batch", "--quiet", "--decrypt"]);
+                if let Some(identity) = &self.identity {
+                    command.arg("--homedir").arg(identity);
+                }
+                command
+            }
+        }
+    }
+}
+
+impl Decryptor for CommandDecryptor {
+    fn decrypt(&self, encryption: Encryption, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        let mut child = self
+            .command(encryption)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("{} is not available: {}", encryption, err)))?;
+
+        // Feeding stdin from another thread keeps large outputs from blocking the child
+        let mut stdin = child.stdin.take().expect("Stdin is piped");
+        let ciphertext = ciphertext.to_vec();
+        let writer = thread::spawn(move || stdin.write_all(&ciphertext));
+
+        let output = child.wait_with_output()?;
+        let written = writer.join().expect("Writer thread does not panic");
+
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "{} decryption failed ({}): {}",
+                encryption,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        written?;
+
+        Ok(output.stdout)
+    }
+}
+
+/// Decryptor for tests that XORs the content with the key
+///
+/// Content produced by `encrypt` with another key fails to decrypt, the
+/// same way real tools reject a wrong identity.
+#[cfg(test)]
+#[derive(Clone, Debug)]
+pub struct FakeDecryptor {
+    key: Vec<u8>,
+}
+
+#[cfg(test)]
+const FAKE_MAGIC: &[u8] = b"rinku-fake:";
+
+#[cfg(test)]
+impl FakeDecryptor {
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "Key can not be empty");
+        FakeDecryptor { key: key.to_vec() }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        self.xor(&[FAKE_MAGIC, plaintext].concat())
+    }
+
+    fn xor(&self, content: &[u8]) -> Vec<u8> {
+        content
+            .iter()
+            .zip(self.key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl Decryptor for FakeDecryptor {
+    fn decrypt(&self, _encryption: Encryption, ciphertext: &[u8]) -> io::Result<Vec<u8>> {
+        match self.xor(ciphertext).strip_prefix(FAKE_MAGIC) {
+            Some(plaintext) => Ok(plaintext.to_vec()),
+            None => Err(io::Error::new(io::ErrorKind::InvalidData, "no matching key")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decryptor, FakeDecryptor};
+    use crate::linkfile::Encryption;
+
+    #[test]
+    fn fake_decryptor_rejects_wrong_key() {
+        let ciphertext = FakeDecryptor::new(b"key").encrypt(b"machine example.com");
+
+        let plaintext = FakeDecryptor::new(b"key").decrypt(Encryption::Age, &ciphertext);
+        assert_eq!(plaintext.unwrap(), b"machine example.com");
+
+        let plaintext = FakeDecryptor::new(b"other").decrypt(Encryption::Age, &ciphertext);
+        assert!(plaintext.is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+
+use crate::drift::{self, BaseStore};
+use crate::filesystem::FileSystem;
+use crate::linker::{LinkResult, LinkState, TargetRoot};
+use crate::linkfile::Dotfiles;
+use crate::plan::Action;
+
+/// Link created by rinku during one of the previous runs
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub source: PathBuf,
+    pub target: PathBuf,
+}
+
+/// Copy deployed by rinku, e.g. a decrypted secret
+///
+/// The hash of the deployed content tells local edits from upstream changes
+/// later, the content itself is kept in the base store only when
+/// `[meta] merge_base` is set, see `drift` module.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CopyRecord {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// SHA-256 of the deployed content
+    pub hash: String,
+}
+
+/// Persistent record of what rinku has done for a particular linkfile
+///
+/// Stored outside of the dotfiles repository in the local data directory,
+/// e.g. `~/.local/share/rinku/`, one file per linkfile.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// Parent directories created for the links
+    #[serde(default)]
+    pub directories: Vec<PathBuf>,
+    #[serde(rename = "link", default)]
+    pub links: Vec<LinkRecord>,
+    #[serde(rename = "copy", default)]
+    pub copies: Vec<CopyRecord>,
+}
+
+impl State {
+    pub fn load(path: &Path) -> io::Result<State> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(State::default()),
+            Err(err) => return Err(err),
+        };
+
+        toml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let content = toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, content)
+    }
+
+    /// Remembers links that point to the right sources after the linkage
+    /// and directories created for them
+    pub fn record_links(&mut self, link_states: &[LinkState]) {
+        for link_state in link_states {
+            for action in &link_state.actions {
+                if let Action::CreateParent { path } = action {
+                    if !self.directories.contains(path) {
+                        self.directories.push(path.clone());
+                    }
+                }
+            }
+
+            // Copies are recorded along with their content by `drift::record_copies`
+            if link_state.task.encryption.is_some() {
+                continue;
+            }
+            if let LinkResult::Success | LinkResult::Existed = link_state.result {
+                self.forget_link(&link_state.task.target);
+                self.links.push(LinkRecord {
+                    source: link_state.task.source.clone(),
+                    target: link_state.task.target.clone(),
+                });
+            }
+        }
+    }
+
+    /// Remembers links and copies made by the linkage of the dotfiles, the
+    /// content of the copies goes to the base store of the linkfile
+    ///
+    /// The state of an alternate target root is never saved, so its copies
+    /// must not touch the base store kept for the real one.
+    pub fn record_linkage(
+        &mut self,
+        fs: &dyn FileSystem,
+        dotfiles: &Dotfiles,
+        target_root: &TargetRoot,
+        link_states: &[LinkState],
+    ) -> io::Result<()> {
+        self.record_links(link_states);
+        match base_dir(&dotfiles.linkfile_path) {
+            Some(base_dir) if target_root.is_default() => {
+                let store = BaseStore::new(fs, &base_dir, dotfiles.linkfile.meta.merge_base);
+                drift::record_copies(&store, self, link_states)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn forget_link(&mut self, target: &Path) {
+        self.links.retain(|record| record.target != target);
+    }
+
+    pub fn copy(&self, target: &Path) -> Option<&CopyRecord> {
+        self.copies.iter().find(|record| record.target == target)
+    }
+
+    /// Hashes of the deployed copies by their targets, see
+    /// `LinkageOptions::deployed`
+    pub fn deployed(&self) -> HashMap<PathBuf, String> {
+        self.copies.iter().map(|record| (record.target.clone(), record.hash.clone())).collect()
+    }
+
+    pub fn record_copy(&mut self, record: CopyRecord) {
+        self.copies.retain(|copy| copy.target != record.target);
+        self.copies.push(record);
+    }
+
+    pub fn forget_directory(&mut self, path: &Path) {
+        self.directories.retain(|directory| directory != path);
+    }
+}
+
+/// Location of the state file that belongs to the linkfile
+pub fn state_path(linkfile: &Path) -> Option<PathBuf> {
+    data_path(linkfile, "state.toml")
+}
+
+/// Directory of the base store that belongs to the linkfile
+pub fn base_dir(linkfile: &Path) -> Option<PathBuf> {
+    data_path(linkfile, "base")
+}
+
+fn data_path(linkfile: &Path, extension: &str) -> Option<PathBuf> {
+    let name: String = linkfile
+        .to_string_lossy()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect();
+
+    Some(
+        dirs::data_local_dir()?
+            .join("rinku")
+            .join(format!("{}.{}", name.trim_start_matches('_'), extension)),
+    )
+}
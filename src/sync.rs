@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use crate::error::Error;
+use crate::filesystem::RealFileSystem;
+use crate::git::{self, Change};
+use crate::hooks::{HookRun, HookRunner};
+use crate::linker::{self, LinkageOptions, LinkageResult, Mode};
+use crate::linkfile::Dotfiles;
+use crate::prune::{self, DirectoryState, PruneState};
+use crate::state::State;
+
+/// Outcome of the sync
+#[derive(Debug)]
+pub struct Synced {
+    /// Uncommitted changes of the sources found before pulling
+    pub changes: Vec<Change>,
+    pub result: LinkageResult,
+    /// Links the pulled linkfile does not produce anymore, empty unless
+    /// pruning was asked for
+    pub prune_states: Vec<PruneState>,
+    pub directory_states: Vec<DirectoryState>,
+    pub hook_runs: Vec<HookRun>,
+}
+
+/// Pulls the linkfile repository, links what it brought and, with `prune`,
+/// prunes what it took away
+///
+/// Uncommitted changes of the sources are only reported, conflicts in them
+/// stop the sync before anything is pulled. The linkfile is read again after
+/// pulling as it may have changed too. The dry mode is refused as pulling
+/// would change the repository anyway.
+pub fn sync(dotfiles: &Dotfiles, options: &LinkageOptions, prune: bool, state: &mut State) -> Result<Synced, Error> {
+    if options.mode == Mode::Dry {
+        return Err(Error::DrySync);
+    }
+    let options = &LinkageOptions { deployed: state.deployed(), ..options.clone() };
+
+    let toplevel = git::toplevel(&dotfiles.root).map_err(Error::Git)?;
+    let sources: Vec<PathBuf> = linker::examine(dotfiles, options)?
+        .into_iter()
+        .map(|link_task| link_task.source)
+        .collect();
+    let changes: Vec<Change> = git::changes(&toplevel)
+        .map_err(Error::Git)?
+        .into_iter()
+        .filter(|change| sources.iter().any(|source| change.path.starts_with(source)))
+        .collect();
+
+    let conflicts: Vec<PathBuf> = changes
+        .iter()
+        .filter(|change| change.conflicted)
+        .map(|change| change.path.clone())
+        .collect();
+    if !conflicts.is_empty() {
+        return Err(Error::MergeConflicts(conflicts));
+    }
+
+    git::pull(&toplevel).map_err(Error::Git)?;
+
+    let dotfiles = dotfiles.reload()?;
+    let result = linker::apply(&dotfiles, linker::plan(&dotfiles, options)?);
+    if let LinkageResult::Completed(link_states, _) = &result {
+        state
+            .record_linkage(&RealFileSystem, &dotfiles, &options.target_root, link_states)
+            .map_err(Error::State)?;
+    }
+
+    if !prune {
+        let (prune_states, directory_states, hook_runs) = (Vec::new(), Vec::new(), Vec::new());
+        return Ok(Synced { changes, result, prune_states, directory_states, hook_runs });
+    }
+
+    let link_tasks = linker::examine(&dotfiles, options)?;
+    let stale_links = prune::find_stale_links(&RealFileSystem, &dotfiles, &options.target_root, state)
+        .map_err(Error::State)?;
+    let hook_runner = HookRunner::new(&dotfiles.root, options.mode, &dotfiles.linkfile.meta.hooks);
+    let (prune_states, hook_runs) =
+        prune::prune_links(&RealFileSystem, options.mode, stale_links, &link_tasks, &hook_runner, state);
+    let directory_states = prune::prune_directories(&RealFileSystem, options.mode, &prune_states, state);
+
+    Ok(Synced { changes, result, prune_states, directory_states, hook_runs })
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::linker::LinkResult;
+    use crate::prune::PruneResult;
+    use std::path::Path;
+    use std::{fs, process};
+
+    fn run(dir: &Path, args: &[&str]) {
+        let status = process::Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_AUTHOR_NAME", "rinku")
+            .env("GIT_AUTHOR_EMAIL", "rinku@localhost")
+            .env("GIT_COMMITTER_NAME", "rinku")
+            .env("GIT_COMMITTER_EMAIL", "rinku@localhost")
+            .stdout(process::Stdio::null())
+            .stderr(process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    /// Commits the linkfile linking the sources into the home directory
+    fn commit(repository: &Path, home: &Path, sources: &[&str]) {
+        let mut linkfile = String::new();
+        for source in sources {
+            fs::write(repository.join(source), source).unwrap();
+            let target = home.join(format!(".{}", source));
+            linkfile += &format!("[[link]]\nsource = '{}'\ntarget = '{}'\n", source, target.display());
+        }
+        fs::write(repository.join("dotfiles.toml"), linkfile).unwrap();
+        run(repository, &["add", "--all"]);
+        run(repository, &["commit", "--quiet", "-m", "update"]);
+    }
+
+    fn sync_with(clone: &Path, mode: Mode, prune: bool, state: &mut State) -> Result<Synced, Error> {
+        let dotfiles = Dotfiles::load(&clone.join("dotfiles.toml")).unwrap();
+        let options = LinkageOptions { mode, ..LinkageOptions::default() };
+        sync(&dotfiles, &options, prune, state)
+    }
+
+    fn sync_lazily(clone: &Path, prune: bool, state: &mut State) -> Synced {
+        sync_with(clone, Mode::Lazy, prune, state).unwrap()
+    }
+
+    /// Upstream with the vimrc entry and its clone
+    fn repositories(temp: &Path) -> (PathBuf, PathBuf, PathBuf) {
+        let (upstream, clone, home) = (temp.join("upstream"), temp.join("clone"), temp.join("home"));
+        fs::create_dir_all(&upstream).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        run(&upstream, &["init", "--quiet"]);
+        commit(&upstream, &home, &["vimrc"]);
+        run(temp, &["clone", "--quiet", "upstream", "clone"]);
+        (upstream, clone, home)
+    }
+
+    fn linked(synced: &Synced) -> Vec<PathBuf> {
+        match &synced.result {
+            LinkageResult::Completed(link_states, _) => link_states
+                .iter()
+                .filter(|link_state| matches!(link_state.result, LinkResult::Success))
+                .map(|link_state| link_state.task.target.clone())
+                .collect(),
+            result => panic!("Linkage was not completed: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn pulled_entries_are_linked_and_removed_ones_pruned() {
+        let temp = tempfile::tempdir().unwrap();
+        let temp = temp.path().canonicalize().unwrap();
+        let (upstream, clone, home) = repositories(&temp);
+
+        let mut state = State::default();
+        let synced = sync_lazily(&clone, true, &mut state);
+        assert_eq!(linked(&synced), vec![home.join(".vimrc")]);
+        assert!(synced.prune_states.is_empty());
+
+        commit(&upstream, &home, &["zshrc"]);
+        fs::write(clone.join("vimrc"), "edited").unwrap();
+        let synced = sync_lazily(&clone, true, &mut state);
+        assert_eq!(synced.changes, vec![Change { path: clone.join("vimrc"), conflicted: false }]);
+        assert_eq!(linked(&synced), vec![home.join(".zshrc")]);
+        assert_eq!(synced.prune_states.len(), 1);
+        assert_eq!(synced.prune_states[0].link.target, home.join(".vimrc"));
+        assert!(matches!(synced.prune_states[0].result, PruneResult::Removed));
+        assert!(fs::symlink_metadata(home.join(".vimrc")).is_err());
+    }
+
+    #[test]
+    fn removed_entries_are_kept_without_prune() {
+        let temp = tempfile::tempdir().unwrap();
+        let temp = temp.path().canonicalize().unwrap();
+        let (upstream, clone, home) = repositories(&temp);
+
+        let mut state = State::default();
+        sync_lazily(&clone, false, &mut state);
+        commit(&upstream, &home, &["zshrc"]);
+        let synced = sync_lazily(&clone, false, &mut state);
+        assert_eq!(linked(&synced), vec![home.join(".zshrc")]);
+        assert!(synced.prune_states.is_empty());
+        assert!(fs::symlink_metadata(home.join(".vimrc")).is_ok());
+    }
+
+    #[test]
+    fn dry_sync_is_refused_before_pulling() {
+        let temp = tempfile::tempdir().unwrap();
+        let temp = temp.path().canonicalize().unwrap();
+        let (upstream, clone, home) = repositories(&temp);
+        commit(&upstream, &home, &["zshrc"]);
+
+        let result = sync_with(&clone, Mode::Dry, false, &mut State::default());
+        assert!(matches!(result, Err(Error::DrySync)));
+        assert!(!clone.join("zshrc").exists());
+    }
+}
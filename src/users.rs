@@ -0,0 +1,63 @@
+use std::io;
+use std::path::PathBuf;
+
+/// Home directory of the user from the system user database
+#[cfg(target_family = "unix")]
+pub fn home_dir(name: &str) -> Option<PathBuf> {
+    nix::unistd::User::from_name(name).ok().flatten().map(|user| user.dir)
+}
+
+#[cfg(target_family = "windows")]
+pub fn home_dir(_name: &str) -> Option<PathBuf> {
+    None
+}
+
+/// Resolves user name or numeric uid
+#[cfg(target_family = "unix")]
+pub fn user_id(user: &str) -> io::Result<u32> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    match nix::unistd::User::from_name(user).map_err(io::Error::from)? {
+        Some(user) => Ok(user.uid.as_raw()),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown user: {}", user),
+        )),
+    }
+}
+
+#[cfg(target_family = "windows")]
+pub fn user_id(_user: &str) -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "ownership is not supported"))
+}
+
+/// Resolves group name or numeric gid
+#[cfg(target_family = "unix")]
+pub fn group_id(group: &str) -> io::Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    match nix::unistd::Group::from_name(group).map_err(io::Error::from)? {
+        Some(group) => Ok(group.gid.as_raw()),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("unknown group: {}", group),
+        )),
+    }
+}
+
+#[cfg(target_family = "windows")]
+pub fn group_id(_group: &str) -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "ownership is not supported"))
+}
+
+#[cfg(target_family = "unix")]
+pub fn is_root() -> bool {
+    nix::unistd::geteuid().is_root()
+}
+
+#[cfg(target_family = "windows")]
+pub fn is_root() -> bool {
+    false
+}
@@ -0,0 +1,214 @@
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use notify::{RecursiveMode, Watcher};
+
+use crate::error::Error;
+use crate::filesystem::RealFileSystem;
+use crate::linker::{self, LinkTask, LinkageOptions, LinkageResult, TargetState};
+use crate::linkfile::Dotfiles;
+use crate::printer::{self, Report};
+use crate::state::State;
+
+enum Signal {
+    Changed,
+    Interrupted,
+}
+
+/// Runs `relink` once and then after every change under the `root`
+///
+/// Changes are debounced, `relink` runs once the tree stays quiet for the
+/// `debounce` interval. Changes inside of `.git` directories are ignored.
+/// Returns after SIGINT, `relink` in progress is never interrupted.
+pub fn watch<F: FnMut()>(root: &Path, debounce: Duration, relink: F) -> Result<(), Error> {
+    let (sender, receiver) = mpsc::channel();
+
+    let interrupt_sender = sender.clone();
+    ctrlc::set_handler(move || {
+        let _ = interrupt_sender.send(Signal::Interrupted);
+    })
+    .map_err(|e| Error::Watch(io::Error::other(e)))?;
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Errors of particular events are not fatal, the next change is picked up anyway
+        if let Ok(event) = event {
+            if is_relevant(&event) {
+                let _ = sender.send(Signal::Changed);
+            }
+        }
+    })
+    .map_err(watch_error)?;
+    watcher.watch(root, RecursiveMode::Recursive).map_err(watch_error)?;
+
+    debounced(&receiver, debounce, relink);
+    Ok(())
+}
+
+/// Runs `relink` once and then whenever the signals of changes stop coming
+/// for the `debounce` interval, returns on interruption
+fn debounced<F: FnMut()>(receiver: &Receiver<Signal>, debounce: Duration, mut relink: F) {
+    relink();
+
+    loop {
+        match receiver.recv() {
+            Ok(Signal::Changed) => {}
+            Ok(Signal::Interrupted) | Err(_) => return,
+        }
+
+        loop {
+            match receiver.recv_timeout(debounce) {
+                Ok(Signal::Changed) => {}
+                Ok(Signal::Interrupted) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => break,
+            }
+        }
+
+        relink();
+    }
+}
+
+/// Single pass of the watch mode, the linkfile is read anew every time
+///
+/// Conflicts are reported once, when they appear, `conflicts` keeps the
+/// targets reported by the previous passes.
+pub fn relink(
+    out: &mut dyn Write,
+    dotfiles: &Dotfiles,
+    options: &LinkageOptions,
+    report: &Report,
+    state: &mut State,
+    conflicts: &mut Vec<PathBuf>,
+) -> Result<(), Error> {
+    let dotfiles = dotfiles.reload()?;
+    // Copies deployed by the previous pass are not edits
+    let options = &LinkageOptions { deployed: state.deployed(), ..options.clone() };
+
+    let is_conflict = |link_task: &LinkTask| {
+        matches!(
+            link_task.target_state,
+            TargetState::AlienNode | TargetState::AlienLink | TargetState::DanglingLink | TargetState::Modified
+        )
+    };
+    let current: Vec<LinkTask> = linker::examine(&dotfiles, options)?
+        .into_iter()
+        .filter(is_conflict)
+        .collect();
+    let new: Vec<LinkTask> = current
+        .iter()
+        .filter(|link_task| !conflicts.contains(&link_task.target))
+        .cloned()
+        .collect();
+    *conflicts = current.into_iter().map(|link_task| link_task.target).collect();
+    printer::present_link_tasks(out, &new, report).map_err(Error::Output)?;
+
+    let plan = linker::plan(&dotfiles, options)?;
+    if plan.links.iter().all(|planned_link| planned_link.actions.is_empty()) {
+        return Ok(());
+    }
+
+    let result = linker::apply(&dotfiles, plan);
+    printer::present_result(out, &result, report).map_err(Error::Output)?;
+    if let LinkageResult::Completed(link_states, _) = &result {
+        state
+            .record_linkage(&RealFileSystem, &dotfiles, &options.target_root, link_states)
+            .map_err(Error::State)?;
+    }
+
+    Ok(())
+}
+
+fn is_relevant(event: &notify::Event) -> bool {
+    let is_in_git = |path: &Path| path.components().any(|c| c == Component::Normal(".git".as_ref()));
+    !event.kind.is_access() && event.paths.iter().any(|path| !is_in_git(path))
+}
+
+fn watch_error(err: notify::Error) -> Error {
+    Error::Watch(io::Error::other(err))
+}
+
+#[cfg(all(test, target_family = "unix"))]
+mod tests {
+    use super::*;
+    use crate::linker::Mode;
+    use notify::event::{AccessKind, CreateKind, EventKind, ModifyKind};
+    use std::fs;
+    use std::time::Instant;
+
+    fn event(kind: EventKind, paths: &[&str]) -> notify::Event {
+        paths.iter().fold(notify::Event::new(kind), |event, path| event.add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn changes_outside_of_git_are_relevant() {
+        let modify = EventKind::Modify(ModifyKind::Any);
+        assert!(is_relevant(&event(modify, &["/dotfiles/vimrc"])));
+        assert!(is_relevant(&event(EventKind::Create(CreateKind::File), &["/dotfiles/.gitignore"])));
+        assert!(is_relevant(&event(modify, &["/dotfiles/.git/index", "/dotfiles/vimrc"])));
+        assert!(!is_relevant(&event(modify, &["/dotfiles/.git/index"])));
+        assert!(!is_relevant(&event(modify, &["/dotfiles/nvim/.git/HEAD"])));
+        assert!(!is_relevant(&event(EventKind::Access(AccessKind::Any), &["/dotfiles/vimrc"])));
+    }
+
+    #[test]
+    fn burst_of_changes_relinks_once() {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..3 {
+            sender.send(Signal::Changed).unwrap();
+        }
+        let debounce = Duration::from_millis(50);
+
+        let mut relinks = Vec::new();
+        let start = Instant::now();
+        debounced(&receiver, debounce, || {
+            relinks.push(start.elapsed());
+            match relinks.len() {
+                2 => sender.send(Signal::Changed).unwrap(),
+                3 => sender.send(Signal::Interrupted).unwrap(),
+                _ => {}
+            }
+        });
+
+        assert_eq!(relinks.len(), 3);
+        assert!(relinks[1] >= debounce, "{:?}", relinks);
+        assert!(relinks[2] - relinks[1] >= debounce, "{:?}", relinks);
+    }
+
+    #[test]
+    fn relink_reports_conflicts_once_and_links_new_entries() {
+        printer::set_colors(false);
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().canonicalize().unwrap();
+        let home = root.join("home");
+        fs::create_dir_all(&home).unwrap();
+        fs::write(root.join("vimrc"), "").unwrap();
+        fs::write(root.join("zshrc"), "").unwrap();
+        fs::write(home.join(".vimrc"), "user's own").unwrap();
+
+        let link = |source: &str| format!("[[link]]\nsource = '{0}'\ntarget = '{1}/.{0}'\n", source, home.display());
+        fs::write(root.join("dotfiles.toml"), link("vimrc")).unwrap();
+        let dotfiles = Dotfiles::load(&root.join("dotfiles.toml")).unwrap();
+        let options = LinkageOptions { mode: Mode::Lazy, ..LinkageOptions::default() };
+        let report = Report::default();
+        let mut state = State::default();
+        let mut conflicts = Vec::new();
+
+        let mut relink = || {
+            let mut out = Vec::new();
+            relink(&mut out, &dotfiles, &options, &report, &mut state, &mut conflicts).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert!(relink().contains("ALIEN"));
+        assert!(!relink().contains("ALIEN"));
+
+        // Linkfile is read on every pass
+        fs::write(root.join("dotfiles.toml"), link("vimrc") + &link("zshrc")).unwrap();
+        let output = relink();
+        assert!(!output.contains("ALIEN"), "{}", output);
+        assert!(output.contains(".zshrc"), "{}", output);
+        assert!(fs::symlink_metadata(home.join(".zshrc")).unwrap().file_type().is_symlink());
+        assert_eq!(conflicts, vec![home.join(".vimrc")]);
+    }
+}